@@ -10,7 +10,7 @@ fn main() -> Result<(), Error> {
 
     tonic_prost_build::configure()
         .file_descriptor_set_path(out_dir.join("feeds_descriptor.bin"))
-        .compile_protos(&["src/feeds.proto"], &["src"])?;
+        .compile_protos(&["src/feeds.proto", "src/events.proto"], &["src"])?;
 
     Ok(())
 }
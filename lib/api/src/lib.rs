@@ -3,3 +3,7 @@ pub mod feeds {
 
     tonic::include_proto!("bzd.feeds.feeds");
 }
+
+pub mod events {
+    tonic::include_proto!("bzd.feeds.events");
+}
@@ -15,6 +15,11 @@ pub enum Tasks {
     TaskId,
     Payload,
     LockedAt,
+    LockedBy,
+    Status,
+    Attempts,
+    LastError,
+    NextRunAt,
 }
 
 #[derive(DeriveIden)]
@@ -23,4 +28,48 @@ pub enum TopicsUsers {
     TopicUserId,
     TopicId,
     UserId,
+    EventAt,
+    EventSeq,
+    DeletedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum MessagesTopics {
+    Table,
+    MessageTopicId,
+    MessageId,
+    TopicId,
+    EntryId,
+    PublishedAt,
+    FanoutOnRead,
+}
+
+#[derive(DeriveIden)]
+pub enum Topics {
+    Table,
+    TopicId,
+    FanoutOnRead,
+    CheckedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum Inbox {
+    Table,
+    EventId,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum Outbox {
+    Table,
+    EventId,
+    Tp,
+    Payload,
+}
+
+#[derive(DeriveIden)]
+pub enum ReadMarkers {
+    Table,
+    UserId,
+    EntryId,
 }
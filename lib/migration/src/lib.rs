@@ -5,6 +5,16 @@ mod entities;
 mod m20251218_164925_create_entries;
 mod m20251219_084835_create_topics_users;
 mod m20251219_091509_create_tasks;
+mod m20261018_093412_create_messages_topics;
+mod m20261018_160233_add_retries_to_tasks;
+mod m20261018_183140_add_fanout_on_read_to_messages_topics;
+mod m20261018_202518_add_locked_by_to_tasks;
+mod m20261018_213547_create_inbox;
+mod m20261018_221406_add_tombstones_to_topics_users;
+mod m20261018_230212_create_outbox;
+mod m20261018_235104_create_read_markers;
+mod m20261018_235812_create_topics;
+mod m20261018_235930_add_message_id_index_to_entries;
 
 pub struct Migrator;
 
@@ -15,6 +25,16 @@ impl MigratorTrait for Migrator {
             Box::new(m20251218_164925_create_entries::Migration),
             Box::new(m20251219_084835_create_topics_users::Migration),
             Box::new(m20251219_091509_create_tasks::Migration),
+            Box::new(m20261018_093412_create_messages_topics::Migration),
+            Box::new(m20261018_160233_add_retries_to_tasks::Migration),
+            Box::new(m20261018_183140_add_fanout_on_read_to_messages_topics::Migration),
+            Box::new(m20261018_202518_add_locked_by_to_tasks::Migration),
+            Box::new(m20261018_213547_create_inbox::Migration),
+            Box::new(m20261018_221406_add_tombstones_to_topics_users::Migration),
+            Box::new(m20261018_230212_create_outbox::Migration),
+            Box::new(m20261018_235104_create_read_markers::Migration),
+            Box::new(m20261018_235812_create_topics::Migration),
+            Box::new(m20261018_235930_add_message_id_index_to_entries::Migration),
         ]
     }
 }
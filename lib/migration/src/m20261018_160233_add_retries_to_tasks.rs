@@ -0,0 +1,59 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::entities::Tasks;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Tasks::Table)
+                    .add_column(string(Tasks::Status).default("active"))
+                    .add_column(integer(Tasks::Attempts).default(0))
+                    .add_column(text_null(Tasks::LastError))
+                    .add_column(timestamp_null(Tasks::NextRunAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("tasks_status_task_id_idx")
+                    .table(Tasks::Table)
+                    .col(Tasks::Status)
+                    .col(Tasks::TaskId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("tasks_status_task_id_idx")
+                    .table(Tasks::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Tasks::Table)
+                    .drop_column(Tasks::Status)
+                    .drop_column(Tasks::Attempts)
+                    .drop_column(Tasks::LastError)
+                    .drop_column(Tasks::NextRunAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
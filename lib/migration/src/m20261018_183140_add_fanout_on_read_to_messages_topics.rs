@@ -0,0 +1,52 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::entities::{MessagesTopics, TopicsUsers};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MessagesTopics::Table)
+                    .add_column(boolean(MessagesTopics::FanoutOnRead).default(false))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("topics_users_user_id_idx")
+                    .table(TopicsUsers::Table)
+                    .col(TopicsUsers::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("topics_users_user_id_idx")
+                    .table(TopicsUsers::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MessagesTopics::Table)
+                    .drop_column(MessagesTopics::FanoutOnRead)
+                    .to_owned(),
+            )
+            .await
+    }
+}
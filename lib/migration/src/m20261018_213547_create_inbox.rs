@@ -0,0 +1,37 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::entities::Inbox;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                table_auto(Inbox::Table)
+                    .col(string(Inbox::EventId).primary_key())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("inbox_created_at_idx")
+                    .table(Inbox::Table)
+                    .col(Inbox::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Inbox::Table).to_owned())
+            .await
+    }
+}
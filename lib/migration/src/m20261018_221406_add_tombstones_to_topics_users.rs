@@ -0,0 +1,46 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::entities::TopicsUsers;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(TopicsUsers::Table)
+                    .add_column(timestamp(TopicsUsers::EventAt).default(Expr::current_timestamp()))
+                    .add_column(big_integer(TopicsUsers::EventSeq).default(0))
+                    .add_column(timestamp_null(TopicsUsers::DeletedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(TopicsUsers::Table)
+                    .and_where(Expr::col(TopicsUsers::DeletedAt).is_not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(TopicsUsers::Table)
+                    .drop_column(TopicsUsers::EventAt)
+                    .drop_column(TopicsUsers::EventSeq)
+                    .drop_column(TopicsUsers::DeletedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
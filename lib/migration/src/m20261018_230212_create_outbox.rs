@@ -0,0 +1,29 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::entities::Outbox;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                table_auto(Outbox::Table)
+                    .col(uuid(Outbox::EventId).primary_key())
+                    .col(string(Outbox::Tp))
+                    .col(binary(Outbox::Payload))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Outbox::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,28 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::entities::ReadMarkers;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                table_auto(ReadMarkers::Table)
+                    .col(uuid(ReadMarkers::UserId).primary_key())
+                    .col(uuid(ReadMarkers::EntryId))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ReadMarkers::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,29 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+use crate::entities::Topics;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                table_auto(Topics::Table)
+                    .col(uuid(Topics::TopicId).primary_key())
+                    .col(boolean(Topics::FanoutOnRead).default(false))
+                    .col(timestamp(Topics::CheckedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Topics::Table).to_owned())
+            .await
+    }
+}
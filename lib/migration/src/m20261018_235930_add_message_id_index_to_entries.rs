@@ -0,0 +1,33 @@
+use sea_orm_migration::prelude::*;
+
+use crate::entities::Entries;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_index(
+                Index::create()
+                    .name("entries_message_id_entry_id_idx")
+                    .table(Entries::Table)
+                    .col(Entries::MessageId)
+                    .col(Entries::EntryId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("entries_message_id_entry_id_idx")
+                    .table(Entries::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -1,4 +1,3 @@
-use axum::Router;
 use bzd_lib::error::Error;
 use bzd_lib::settings::{HttpSettings, Settings as _};
 use tokio::try_join;
@@ -28,6 +27,16 @@ pub async fn run() -> Result<(), Error> {
     Ok(())
 }
 
+pub async fn replay() -> Result<(), Error> {
+    let settings = AppSettings::new()?;
+    let state = AppState::new(settings).await?;
+
+    info!("replay: started");
+    feeds::replay(&state).await?;
+
+    Ok(())
+}
+
 async fn http_and_grpc(state: &AppState, settings: &HttpSettings) -> Result<(), Error> {
     let reflection_service = tonic_reflection::server::Builder::configure()
         .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
@@ -36,12 +45,12 @@ async fn http_and_grpc(state: &AppState, settings: &HttpSettings) -> Result<(),
 
     let (_, health_service) = tonic_health::server::health_reporter();
 
-    let router = Router::new().with_state(());
-    let routes = Routes::from(router);
+    let routes = Routes::from(feeds::router(state));
     let router = routes
         .add_service(reflection_service)
         .add_service(health_service)
         .add_service(feeds::service(state))
+        .add_service(feeds::tasks_service(state))
         .into_axum_router();
 
     let listener = tokio::net::TcpListener::bind(&settings.endpoint).await?;
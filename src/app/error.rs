@@ -1,28 +1,78 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
 use thiserror::Error;
 use tonic::Status;
 
 impl From<AppError> for Status {
     fn from(error: AppError) -> Self {
         match error {
+            AppError::NotFound => Self::not_found(error.to_string()),
+            AppError::Uuid(_)
+            | AppError::Validation(_)
+            | AppError::InvalidPageToken
+            | AppError::CursorRemoved => Self::invalid_argument(error.to_string()),
             _ => Self::internal(error.to_string()),
         }
     }
 }
 
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Uuid(_)
+            | AppError::Validation(_)
+            | AppError::InvalidPageToken
+            | AppError::CursorRemoved => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, self.to_string()).into_response()
+    }
+}
+
+impl AppError {
+    // Ошибки, которые могут пройти при повторной доставке
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Db(_) | Self::Sqlx(_))
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("ACK")]
     Encode(#[from] async_nats::Error),
+    #[error("PUBLISH")]
+    Publish(#[from] async_nats::jetstream::context::PublishError),
     #[error("DB")]
     Db(#[from] sea_orm::DbErr),
+    #[error("SQLX")]
+    Sqlx(#[from] sea_orm::sqlx::Error),
     #[error("UUID")]
     Uuid(#[from] uuid::Error),
     #[error("DECODE")]
     Decode(#[from] prost::DecodeError),
     #[error("STRUM")]
     Strum(#[from] strum::ParseError),
+    #[error("CHRONO")]
+    Chrono(#[from] chrono::ParseError),
+    #[error("JSON")]
+    Json(#[from] serde_json::Error),
+    #[error("VALIDATION: {0}")]
+    Validation(#[from] validator::ValidationErrors),
 
     // Ok
+    #[error("NOT_FOUND")]
+    NotFound,
+    #[error("LOCK_LOST")]
+    LockLost,
+    #[error("INVALID_PAGE_TOKEN")]
+    InvalidPageToken,
+    // Старый клиент прислал курсор вместо page_token
+    #[error("CURSOR_REMOVED: use page_token")]
+    CursorRemoved,
     #[error("UNREACHABLE")]
     Unreachable,
 }
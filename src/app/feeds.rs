@@ -1,32 +1,60 @@
-use bzd_flux_api::feeds::feeds_service_server::FeedsServiceServer;
+use axum::Router;
+use bzd_flux_api::feeds::{
+    feeds_service_server::FeedsServiceServer, tasks_service_server::TasksServiceServer,
+};
 use bzd_lib::error::Error;
 use tokio::try_join;
 
-use crate::app::{feeds::grpc::GrpcFeedsService, state::AppState};
+use crate::app::{
+    feeds::grpc::{GrpcFeedsService, GrpcTasksService},
+    state::AppState,
+};
 
 mod grpc;
+mod http;
+pub mod hub;
 mod messaging;
+mod page_token;
 mod processing;
 mod repo;
 mod service;
 pub mod settings;
 pub mod state;
 
+pub fn router(state: &AppState) -> Router {
+    http::router(state.feeds.clone())
+}
+
 pub fn service(state: &AppState) -> FeedsServiceServer<GrpcFeedsService> {
     FeedsServiceServer::new(GrpcFeedsService::new(state.feeds.clone()))
 }
 
+pub fn tasks_service(state: &AppState) -> TasksServiceServer<GrpcTasksService> {
+    TasksServiceServer::new(GrpcTasksService::new(state.feeds.clone()))
+}
+
 pub async fn messaging(state: &AppState) -> Result<(), Error> {
     try_join!(
         messaging::messages_topics(state.feeds.clone()),
-        messaging::topics_users(state.feeds.clone())
+        messaging::topics_users(state.feeds.clone()),
+        messaging::streaming(state.feeds.clone())
     )?;
 
     Ok(())
 }
 
+pub async fn replay(state: &AppState) -> Result<(), Error> {
+    messaging::replay_dead_letters(state.feeds.clone()).await?;
+
+    Ok(())
+}
+
 pub async fn processing(state: &AppState) -> Result<(), Error> {
-    try_join!(processing::tasks(state.feeds.clone()))?;
+    try_join!(
+        processing::tasks(state.feeds.clone()),
+        processing::outbox(state.feeds.clone()),
+        processing::inbox(state.feeds.clone())
+    )?;
 
     Ok(())
 }
@@ -1,6 +1,13 @@
+use std::pin::Pin;
+
 use bzd_flux_api::feeds::{
-    GetUserEntriesRequest, GetUserEntriesResponse, feeds_service_server::FeedsService,
+    GetDeadTasksRequest, GetDeadTasksResponse, GetUnreadCountRequest, GetUnreadCountResponse,
+    GetUserEntriesRequest, GetUserEntriesResponse, MarkAllReadRequest, MarkAllReadResponse,
+    MarkReadRequest, MarkReadResponse, RequeueTaskRequest, RequeueTaskResponse,
+    SubscribeUserEntriesRequest, SubscribeUserEntriesResponse, feeds_service_server::FeedsService,
+    tasks_service_server::TasksService,
 };
+use tokio_stream::Stream;
 use tonic::{Request, Response, Status};
 
 use crate::app::feeds::state::FeedsState;
@@ -25,18 +32,61 @@ impl FeedsService for GrpcFeedsService {
 
         Ok(Response::new(res))
     }
+
+    type SubscribeUserEntriesStream =
+        Pin<Box<dyn Stream<Item = Result<SubscribeUserEntriesResponse, Status>> + Send>>;
+
+    async fn subscribe_user_entries(
+        &self,
+        req: Request<SubscribeUserEntriesRequest>,
+    ) -> Result<Response<Self::SubscribeUserEntriesStream>, Status> {
+        let res = subscribe_user_entries::handler(&self.state, req.into_inner())?;
+
+        Ok(Response::new(Box::pin(res)))
+    }
+
+    async fn mark_read(
+        &self,
+        req: Request<MarkReadRequest>,
+    ) -> Result<Response<MarkReadResponse>, Status> {
+        let res = mark_read::handler(&self.state, req.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn mark_all_read(
+        &self,
+        req: Request<MarkAllReadRequest>,
+    ) -> Result<Response<MarkAllReadResponse>, Status> {
+        let res = mark_all_read::handler(&self.state, req.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn get_unread_count(
+        &self,
+        req: Request<GetUnreadCountRequest>,
+    ) -> Result<Response<GetUnreadCountResponse>, Status> {
+        let res = get_unread_count::handler(&self.state, req.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
 }
 
 mod get_user_entries {
-    use bzd_flux_api::feeds::{GetUserEntriesRequest, GetUserEntriesResponse};
-    use uuid::Uuid;
+    use std::time::SystemTime;
+
+    use bzd_flux_api::feeds::{
+        GetUserEntriesRequest, GetUserEntriesResponse, get_user_entries_request,
+        get_user_entries_response,
+    };
 
     use crate::app::{
         error::AppError,
         feeds::{
             service::{
                 self,
-                get_user_entries::{Request, Response},
+                get_user_entries::{Direction, Filters, Request, Response},
             },
             state::FeedsState,
         },
@@ -46,7 +96,7 @@ mod get_user_entries {
         FeedsState { db, settings, .. }: &FeedsState,
         req: GetUserEntriesRequest,
     ) -> Result<GetUserEntriesResponse, AppError> {
-        let res = service::get_user_entries(&db.conn, &settings, req.try_into()?).await?;
+        let res = service::get_user_entries(&db.conn, settings, req.try_into()?).await?;
 
         Ok(res.into())
     }
@@ -55,6 +105,106 @@ mod get_user_entries {
         type Error = AppError;
 
         fn try_from(req: GetUserEntriesRequest) -> Result<Self, Self::Error> {
+            if req.cursor_entry_id.is_some() {
+                return Err(AppError::CursorRemoved);
+            }
+
+            Ok(Self {
+                user_id: req.user_id().parse()?,
+                direction: req.direction().into(),
+                page_token: req.page_token,
+                filters: Filters::new(
+                    req.topic_ids
+                        .iter()
+                        .map(|it| it.parse())
+                        .collect::<Result<_, _>>()?,
+                ),
+                page_size: req.page_size.map(Into::into),
+            })
+        }
+    }
+
+    impl From<get_user_entries_request::Direction> for Direction {
+        fn from(direction: get_user_entries_request::Direction) -> Self {
+            match direction {
+                get_user_entries_request::Direction::Older => Self::Older,
+                get_user_entries_request::Direction::Newer => Self::Newer,
+            }
+        }
+    }
+
+    impl From<Response> for GetUserEntriesResponse {
+        fn from(res: Response) -> Self {
+            Self {
+                message_ids: res.entries.iter().map(|it| it.message_id.into()).collect(),
+                entries: res
+                    .entries
+                    .iter()
+                    .map(|it| get_user_entries_response::Entry {
+                        entry_id: Some(it.entry_id.into()),
+                        message_id: Some(it.message_id.into()),
+                        topic_ids: res
+                            .entry_topic_ids(it)
+                            .into_iter()
+                            .map(Into::into)
+                            .collect(),
+                        created_at: Some(SystemTime::from(it.created_at.and_utc()).into()),
+                        updated_at: Some(SystemTime::from(it.updated_at.and_utc()).into()),
+                    })
+                    .collect(),
+                has_more: Some(res.has_more),
+                head_page_token: res.head_page_token,
+                tail_page_token: res.tail_page_token,
+            }
+        }
+    }
+}
+
+mod subscribe_user_entries {
+    use bzd_flux_api::feeds::{SubscribeUserEntriesRequest, SubscribeUserEntriesResponse};
+    use tokio::sync::mpsc;
+    use tokio_stream::{Stream, StreamExt as _, wrappers::ReceiverStream};
+    use tonic::Status;
+    use uuid::Uuid;
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            repo::EntryModel,
+            service::{self, subscribe_user_entries::Request},
+            state::FeedsState,
+        },
+    };
+
+    pub fn handler(
+        state: &FeedsState,
+        req: SubscribeUserEntriesRequest,
+    ) -> Result<
+        impl Stream<Item = Result<SubscribeUserEntriesResponse, Status>> + Send + use<>,
+        AppError,
+    > {
+        let req: Request = req.try_into()?;
+        let (tx, rx) = mpsc::channel(state.settings.streaming.buffer);
+
+        let FeedsState {
+            db, settings, hub, ..
+        } = state.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) =
+                service::subscribe_user_entries(&db.conn, &settings, &hub, req, &tx).await
+            {
+                let _ = tx.send(Err(err)).await;
+            }
+        });
+
+        Ok(ReceiverStream::new(rx).map(|res| res.map(Into::into).map_err(Into::into)))
+    }
+
+    impl TryFrom<SubscribeUserEntriesRequest> for Request {
+        type Error = AppError;
+
+        fn try_from(req: SubscribeUserEntriesRequest) -> Result<Self, Self::Error> {
             Ok(Self {
                 user_id: req.user_id().parse()?,
                 cursor_entry_id: req
@@ -66,12 +216,252 @@ mod get_user_entries {
         }
     }
 
-    impl From<Response> for GetUserEntriesResponse {
+    impl From<EntryModel> for SubscribeUserEntriesResponse {
+        fn from(entry: EntryModel) -> Self {
+            Self {
+                entry_id: Some(entry.entry_id.into()),
+                message_id: Some(entry.message_id.into()),
+            }
+        }
+    }
+}
+
+mod mark_read {
+    use bzd_flux_api::feeds::{MarkReadRequest, MarkReadResponse};
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            service::{self, mark_read::Request},
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        FeedsState { db, settings, .. }: &FeedsState,
+        req: MarkReadRequest,
+    ) -> Result<MarkReadResponse, AppError> {
+        service::mark_read(&db.conn, settings, req.try_into()?).await?;
+
+        Ok(MarkReadResponse {})
+    }
+
+    impl TryFrom<MarkReadRequest> for Request {
+        type Error = AppError;
+
+        fn try_from(req: MarkReadRequest) -> Result<Self, Self::Error> {
+            Ok(Self {
+                user_id: req.user_id().parse()?,
+                entry_id: req.entry_id().parse()?,
+            })
+        }
+    }
+}
+
+mod mark_all_read {
+    use bzd_flux_api::feeds::{MarkAllReadRequest, MarkAllReadResponse};
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            service::{self, mark_all_read::Request},
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        FeedsState { db, settings, .. }: &FeedsState,
+        req: MarkAllReadRequest,
+    ) -> Result<MarkAllReadResponse, AppError> {
+        service::mark_all_read(&db.conn, settings, req.try_into()?).await?;
+
+        Ok(MarkAllReadResponse {})
+    }
+
+    impl TryFrom<MarkAllReadRequest> for Request {
+        type Error = AppError;
+
+        fn try_from(req: MarkAllReadRequest) -> Result<Self, Self::Error> {
+            Ok(Self {
+                user_id: req.user_id().parse()?,
+            })
+        }
+    }
+}
+
+mod get_unread_count {
+    use bzd_flux_api::feeds::{
+        GetUnreadCountRequest, GetUnreadCountResponse, get_unread_count_response,
+    };
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            service::{
+                self,
+                get_unread_count::{Request, Response},
+            },
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        FeedsState { db, settings, .. }: &FeedsState,
+        req: GetUnreadCountRequest,
+    ) -> Result<GetUnreadCountResponse, AppError> {
+        let res = service::get_unread_count(&db.conn, settings, req.try_into()?).await?;
+
+        Ok(res.into())
+    }
+
+    impl TryFrom<GetUnreadCountRequest> for Request {
+        type Error = AppError;
+
+        fn try_from(req: GetUnreadCountRequest) -> Result<Self, Self::Error> {
+            Ok(Self {
+                user_id: req.user_id().parse()?,
+                by_topic: req.by_topic(),
+            })
+        }
+    }
+
+    impl From<Response> for GetUnreadCountResponse {
         fn from(res: Response) -> Self {
             Self {
-                message_ids: res.entries.iter().map(|it| it.message_id.into()).collect(),
-                cursor_entry_id: res.cursor_entry.map(|it| it.entry_id.into()),
+                count: Some(res.count),
+                capped: Some(res.capped),
+                topics: res
+                    .topics
+                    .into_iter()
+                    .map(|it| get_unread_count_response::Topic {
+                        topic_id: Some(it.topic_id.into()),
+                        count: Some(it.count),
+                    })
+                    .collect(),
             }
         }
     }
 }
+
+pub struct GrpcTasksService {
+    pub state: FeedsState,
+}
+
+impl GrpcTasksService {
+    pub fn new(state: FeedsState) -> Self {
+        Self { state }
+    }
+}
+
+#[tonic::async_trait]
+impl TasksService for GrpcTasksService {
+    async fn get_dead_tasks(
+        &self,
+        req: Request<GetDeadTasksRequest>,
+    ) -> Result<Response<GetDeadTasksResponse>, Status> {
+        let res = get_dead_tasks::handler(&self.state, req.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+
+    async fn requeue_task(
+        &self,
+        req: Request<RequeueTaskRequest>,
+    ) -> Result<Response<RequeueTaskResponse>, Status> {
+        let res = requeue_task::handler(&self.state, req.into_inner()).await?;
+
+        Ok(Response::new(res))
+    }
+}
+
+mod get_dead_tasks {
+    use bzd_flux_api::feeds::{GetDeadTasksRequest, GetDeadTasksResponse, get_dead_tasks_response};
+    use uuid::Uuid;
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            service::{
+                self,
+                get_dead_tasks::{Request, Response},
+            },
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        FeedsState { db, settings, .. }: &FeedsState,
+        req: GetDeadTasksRequest,
+    ) -> Result<GetDeadTasksResponse, AppError> {
+        let res = service::get_dead_tasks(&db.conn, settings, req.try_into()?).await?;
+
+        res.try_into()
+    }
+
+    impl TryFrom<GetDeadTasksRequest> for Request {
+        type Error = AppError;
+
+        fn try_from(req: GetDeadTasksRequest) -> Result<Self, Self::Error> {
+            Ok(Self {
+                cursor_task_id: req
+                    .cursor_task_id
+                    .as_deref()
+                    .map(Uuid::parse_str)
+                    .transpose()?,
+            })
+        }
+    }
+
+    impl TryFrom<Response> for GetDeadTasksResponse {
+        type Error = AppError;
+
+        fn try_from(res: Response) -> Result<Self, Self::Error> {
+            Ok(Self {
+                tasks: res
+                    .tasks
+                    .into_iter()
+                    .map(|it| {
+                        Ok(get_dead_tasks_response::Task {
+                            task_id: Some(it.task_id.into()),
+                            payload: Some(serde_json::to_string(&it.payload)?),
+                            attempts: Some(it.attempts),
+                            last_error: it.last_error,
+                        })
+                    })
+                    .collect::<Result<_, AppError>>()?,
+                cursor_task_id: res.cursor_task.map(|it| it.task_id.into()),
+            })
+        }
+    }
+}
+
+mod requeue_task {
+    use bzd_flux_api::feeds::{RequeueTaskRequest, RequeueTaskResponse};
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            service::{self, requeue_task::Request},
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        FeedsState { db, .. }: &FeedsState,
+        req: RequeueTaskRequest,
+    ) -> Result<RequeueTaskResponse, AppError> {
+        service::requeue_task(&db.conn, req.try_into()?).await?;
+
+        Ok(RequeueTaskResponse {})
+    }
+
+    impl TryFrom<RequeueTaskRequest> for Request {
+        type Error = AppError;
+
+        fn try_from(req: RequeueTaskRequest) -> Result<Self, Self::Error> {
+            Ok(Self {
+                task_id: req.task_id().parse()?,
+            })
+        }
+    }
+}
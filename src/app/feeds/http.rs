@@ -0,0 +1,362 @@
+use axum::{
+    Router,
+    routing::{get, post},
+};
+
+use crate::app::feeds::state::FeedsState;
+
+pub fn router(state: FeedsState) -> Router {
+    Router::new()
+        .route("/users/{user_id}/entries", get(get_user_entries::handler))
+        .route(
+            "/users/{user_id}/feed/stream",
+            get(stream_user_entries::handler),
+        )
+        .route("/users/{user_id}/read", post(mark_read::handler))
+        .route("/users/{user_id}/read/all", post(mark_all_read::handler))
+        .route("/users/{user_id}/unread", get(get_unread_count::handler))
+        .with_state(state)
+}
+
+mod get_user_entries {
+    use axum::{
+        Json,
+        extract::{Path, Query, State},
+    };
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            service::{
+                self,
+                get_user_entries::{Direction, Filters, Request, Response},
+            },
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        State(FeedsState { db, settings, .. }): State<FeedsState>,
+        Path(user_id): Path<String>,
+        Query(params): Query<Params>,
+    ) -> Result<Json<Body>, AppError> {
+        let res =
+            service::get_user_entries(&db.conn, &settings, (user_id, params).try_into()?).await?;
+
+        Ok(Json(res.into()))
+    }
+
+    #[derive(Deserialize)]
+    pub struct Params {
+        // Заменён page_token, принимается только чтобы отклонить запрос
+        cursor: Option<String>,
+        page_token: Option<String>,
+        direction: Option<ParamsDirection>,
+        page_size: Option<u64>,
+        // Через запятую
+        topic_ids: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ParamsDirection {
+        Older,
+        Newer,
+    }
+
+    #[derive(Serialize)]
+    pub struct Body {
+        message_ids: Vec<Uuid>,
+        entries: Vec<Entry>,
+        has_more: bool,
+        head_page_token: Option<String>,
+        tail_page_token: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    pub struct Entry {
+        entry_id: Uuid,
+        message_id: Uuid,
+        topic_ids: Vec<Uuid>,
+        created_at: String,
+        updated_at: String,
+    }
+
+    impl TryFrom<(String, Params)> for Request {
+        type Error = AppError;
+
+        fn try_from((user_id, params): (String, Params)) -> Result<Self, Self::Error> {
+            if params.cursor.is_some() {
+                return Err(AppError::CursorRemoved);
+            }
+
+            Ok(Self {
+                user_id: user_id.parse()?,
+                page_token: params.page_token,
+                direction: params.direction.map(Into::into).unwrap_or_default(),
+                filters: Filters::new(
+                    params
+                        .topic_ids
+                        .iter()
+                        .flat_map(|it| it.split(','))
+                        .map(Uuid::parse_str)
+                        .collect::<Result<_, _>>()?,
+                ),
+                page_size: params.page_size,
+            })
+        }
+    }
+
+    impl From<ParamsDirection> for Direction {
+        fn from(direction: ParamsDirection) -> Self {
+            match direction {
+                ParamsDirection::Older => Self::Older,
+                ParamsDirection::Newer => Self::Newer,
+            }
+        }
+    }
+
+    impl From<Response> for Body {
+        fn from(res: Response) -> Self {
+            Self {
+                message_ids: res.entries.iter().map(|it| it.message_id).collect(),
+                entries: res
+                    .entries
+                    .iter()
+                    .map(|it| Entry {
+                        entry_id: it.entry_id,
+                        message_id: it.message_id,
+                        topic_ids: res.entry_topic_ids(it),
+                        created_at: it.created_at.and_utc().to_rfc3339(),
+                        updated_at: it.updated_at.and_utc().to_rfc3339(),
+                    })
+                    .collect(),
+                has_more: res.has_more,
+                head_page_token: res.head_page_token,
+                tail_page_token: res.tail_page_token,
+            }
+        }
+    }
+}
+
+mod stream_user_entries {
+    use axum::{
+        extract::{Path, State},
+        http::HeaderMap,
+        response::sse::{Event, KeepAlive, Sse},
+    };
+    use serde::Serialize;
+    use tokio::sync::mpsc;
+    use tokio_stream::{Stream, StreamExt as _, wrappers::ReceiverStream};
+    use uuid::Uuid;
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            repo::EntryModel,
+            service::{self, subscribe_user_entries::Request},
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        State(state): State<FeedsState>,
+        Path(user_id): Path<String>,
+        headers: HeaderMap,
+    ) -> Result<Sse<impl Stream<Item = Result<Event, AppError>>>, AppError> {
+        // Браузер сам присылает id последнего полученного события при переподключении,
+        // без него поток начинается с новых записей
+        let cursor_entry_id = headers
+            .get("last-event-id")
+            .and_then(|it| it.to_str().ok())
+            .map(Uuid::parse_str)
+            .transpose()?;
+
+        let req = Request {
+            user_id: user_id.parse()?,
+            cursor_entry_id,
+        };
+        let (tx, rx) = mpsc::channel(state.settings.streaming.buffer);
+
+        let FeedsState {
+            db, settings, hub, ..
+        } = state;
+
+        tokio::spawn(async move {
+            if let Err(err) =
+                service::subscribe_user_entries(&db.conn, &settings, &hub, req, &tx).await
+            {
+                let _ = tx.send(Err(err)).await;
+            }
+        });
+
+        let stream = ReceiverStream::new(rx).map(|res| res.and_then(TryInto::try_into));
+
+        Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+    }
+
+    #[derive(Serialize)]
+    struct Entry {
+        entry_id: Uuid,
+        message_id: Uuid,
+    }
+
+    impl TryFrom<EntryModel> for Event {
+        type Error = AppError;
+
+        fn try_from(entry: EntryModel) -> Result<Self, Self::Error> {
+            let data = serde_json::to_string(&Entry {
+                entry_id: entry.entry_id,
+                message_id: entry.message_id,
+            })?;
+
+            Ok(Event::default()
+                .id(entry.entry_id.to_string())
+                .event("entry")
+                .data(data))
+        }
+    }
+}
+
+mod mark_read {
+    use axum::{
+        Json,
+        extract::{Path, State},
+    };
+    use serde::Deserialize;
+    use uuid::Uuid;
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            service::{self, mark_read::Request},
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        State(FeedsState { db, settings, .. }): State<FeedsState>,
+        Path(user_id): Path<String>,
+        Json(body): Json<Body>,
+    ) -> Result<(), AppError> {
+        service::mark_read(&db.conn, &settings, (user_id, body).try_into()?).await
+    }
+
+    #[derive(Deserialize)]
+    pub struct Body {
+        entry_id: Uuid,
+    }
+
+    impl TryFrom<(String, Body)> for Request {
+        type Error = AppError;
+
+        fn try_from((user_id, body): (String, Body)) -> Result<Self, Self::Error> {
+            Ok(Self {
+                user_id: user_id.parse()?,
+                entry_id: body.entry_id,
+            })
+        }
+    }
+}
+
+mod mark_all_read {
+    use axum::extract::{Path, State};
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            service::{self, mark_all_read::Request},
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        State(FeedsState { db, settings, .. }): State<FeedsState>,
+        Path(user_id): Path<String>,
+    ) -> Result<(), AppError> {
+        let req = Request {
+            user_id: user_id.parse()?,
+        };
+
+        service::mark_all_read(&db.conn, &settings, req).await
+    }
+}
+
+mod get_unread_count {
+    use axum::{
+        Json,
+        extract::{Path, Query, State},
+    };
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            service::{
+                self,
+                get_unread_count::{Request, Response},
+            },
+            state::FeedsState,
+        },
+    };
+
+    pub async fn handler(
+        State(FeedsState { db, settings, .. }): State<FeedsState>,
+        Path(user_id): Path<String>,
+        Query(params): Query<Params>,
+    ) -> Result<Json<Body>, AppError> {
+        let res =
+            service::get_unread_count(&db.conn, &settings, (user_id, params).try_into()?).await?;
+
+        Ok(Json(res.into()))
+    }
+
+    #[derive(Deserialize)]
+    pub struct Params {
+        by_topic: Option<bool>,
+    }
+
+    #[derive(Serialize)]
+    pub struct Body {
+        count: u64,
+        capped: bool,
+        topics: Vec<Topic>,
+    }
+
+    #[derive(Serialize)]
+    pub struct Topic {
+        topic_id: Uuid,
+        count: u64,
+    }
+
+    impl TryFrom<(String, Params)> for Request {
+        type Error = AppError;
+
+        fn try_from((user_id, params): (String, Params)) -> Result<Self, Self::Error> {
+            Ok(Self {
+                user_id: user_id.parse()?,
+                by_topic: params.by_topic.unwrap_or_default(),
+            })
+        }
+    }
+
+    impl From<Response> for Body {
+        fn from(res: Response) -> Self {
+            Self {
+                count: res.count,
+                capped: res.capped,
+                topics: res
+                    .topics
+                    .into_iter()
+                    .map(|it| Topic {
+                        topic_id: it.topic_id,
+                        count: it.count,
+                    })
+                    .collect(),
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::app::feeds::repo::{EntryModel, MessageTopicModel, TopicUserModel};
+
+// Новые записи лент для потоков, открытых в этом процессе. У каждого пользователя свой канал:
+// рассылка по крупному топику не переполняет потоки всех остальных пользователей
+#[derive(Clone)]
+pub struct Hub {
+    capacity: usize,
+    users: Arc<Mutex<HashMap<Uuid, User>>>,
+}
+
+struct User {
+    tx: broadcast::Sender<EntryModel>,
+    // Подписки на крупные топики: их сообщения не попадают в entries и приходят отдельно
+    topics_users: Vec<TopicUserModel>,
+}
+
+impl Hub {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            users: Arc::default(),
+        }
+    }
+
+    pub fn publish(&self, entries: &[EntryModel]) {
+        let mut users = self.users.lock().unwrap_or_else(PoisonError::into_inner);
+
+        for entry in entries {
+            // Ошибка означает, что все потоки пользователя уже закрыты
+            if let Some(user) = users.get(&entry.user_id)
+                && user.tx.send(entry.clone()).is_err()
+            {
+                users.remove(&entry.user_id);
+            }
+        }
+    }
+
+    pub fn publish_message_topic(&self, message_topic: &MessageTopicModel) {
+        let mut users = self.users.lock().unwrap_or_else(PoisonError::into_inner);
+
+        users.retain(|_, user| {
+            // Как и при чтении, подписчик видит только сообщения после подписки
+            let Some(topic_user) = user.topics_users.iter().find(|it| {
+                it.topic_id == message_topic.topic_id && it.event_at <= message_topic.published_at
+            }) else {
+                return true;
+            };
+
+            let entry = EntryModel::from_message_topic(message_topic.clone(), topic_user);
+
+            user.tx.send(entry).is_ok()
+        });
+    }
+
+    // topics_users заменяют ранее сохранённые: у последнего открытого потока список свежее
+    pub fn subscribe(
+        &self,
+        user_id: Uuid,
+        topics_users: Vec<TopicUserModel>,
+    ) -> broadcast::Receiver<EntryModel> {
+        let mut users = self.users.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Каналы пользователей, которым ничего не пришло после закрытия потоков
+        users.retain(|_, user| user.tx.receiver_count() > 0);
+
+        let user = users.entry(user_id).or_insert_with(|| User {
+            tx: broadcast::channel(self.capacity).0,
+            topics_users: vec![],
+        });
+        user.topics_users = topics_users;
+
+        user.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+    use uuid::Uuid;
+
+    use crate::app::feeds::{
+        hub::Hub,
+        repo::{EntryModel, MessageTopicModel, TopicUserModel},
+    };
+
+    #[test]
+    fn test_ok_publish_only_to_own_user() {
+        let hub = Hub::new(1);
+
+        let user_id = Uuid::now_v7();
+        let mut rx = hub.subscribe(user_id, vec![]);
+
+        let entry = EntryModel {
+            user_id,
+            ..EntryModel::stub()
+        };
+
+        // Чужие записи не занимают место в канале пользователя
+        hub.publish(&[EntryModel::stub(), entry.clone(), EntryModel::stub()]);
+
+        assert_eq!(rx.try_recv().ok(), Some(entry));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_ok_publish_message_topic_to_subscribers() {
+        let hub = Hub::new(1);
+
+        let now = Utc::now().naive_utc();
+        let topic_id = Uuid::now_v7();
+
+        let topic_user = TopicUserModel {
+            event_at: now - TimeDelta::hours(1),
+            ..TopicUserModel::new(Uuid::now_v7(), Uuid::now_v7(), topic_id)
+        };
+        let mut rx = hub.subscribe(topic_user.user_id, vec![topic_user.clone()]);
+
+        // Подписался позже публикации: сообщение ему не положено
+        let late_topic_user = TopicUserModel {
+            event_at: now + TimeDelta::hours(1),
+            ..TopicUserModel::new(Uuid::now_v7(), Uuid::now_v7(), topic_id)
+        };
+        let mut late_rx = hub.subscribe(late_topic_user.user_id, vec![late_topic_user]);
+
+        let mut other_rx = hub.subscribe(Uuid::now_v7(), vec![]);
+
+        let message_topic = MessageTopicModel {
+            fanout_on_read: true,
+            ..MessageTopicModel::new(Uuid::now_v7(), Uuid::now_v7(), topic_id, now)
+        };
+
+        hub.publish_message_topic(&message_topic);
+
+        assert_eq!(
+            rx.try_recv().ok(),
+            Some(EntryModel::from_message_topic(message_topic, &topic_user))
+        );
+        assert!(late_rx.try_recv().is_err());
+        assert!(other_rx.try_recv().is_err());
+    }
+}
@@ -1,22 +1,442 @@
+use std::time::Duration;
+
+use async_nats::{
+    HeaderMap,
+    header::NATS_MESSAGE_ID,
+    jetstream::{
+        self, AckKind,
+        consumer::{Consumer, pull::Config},
+    },
+};
 use bzd_lib::error::Error;
+use chrono::{DateTime, NaiveDateTime, SecondsFormat};
 use futures_lite::StreamExt as _;
-use tracing::error;
-
-use crate::app::feeds::state::FeedsState;
+use tracing::{error, info};
+
+use crate::app::{
+    error::AppError,
+    feeds::{
+        settings::{BatchSettings, DeadLetterSettings},
+        state::FeedsState,
+    },
+    mess::MessState,
+};
+
+const DEAD_LETTER_SUBJECT: &str = "dl_subject";
+const DEAD_LETTER_ERROR: &str = "dl_error";
+const DEAD_LETTER_DELIVERED: &str = "dl_delivered";
+const DEAD_LETTER_HEADERS: [&str; 3] = [
+    DEAD_LETTER_SUBJECT,
+    DEAD_LETTER_ERROR,
+    DEAD_LETTER_DELIVERED,
+];
+// Время и номер первой публикации в стриме; переживают replay, иначе старое событие
+// получит новые время и номер и обгонит более позднее
+const ORIGINAL_PUBLISHED: &str = "orig_published";
+const ORIGINAL_SEQUENCE: &str = "orig_sequence";
+const REPLAY_BATCH_SIZE: usize = 100;
 
 pub async fn messages_topics(state: FeedsState) -> Result<(), Error> {
     let consumer = messages_topics::consumer(&state.mess, &state.settings).await?;
-    let mut messages = consumer.messages().await?;
 
-    while let Some(message) = messages.next().await {
-        if let Err(err) = messages_topics::handler(&state, message?).await {
+    loop {
+        let messages = fetch(&consumer, &state.settings.messaging.batch).await?;
+
+        handle_batch(&state, messages, |reqs| {
+            messages_topics::handler(&state, reqs)
+        })
+        .await;
+    }
+}
+
+async fn fetch(
+    consumer: &Consumer<Config>,
+    settings: &BatchSettings,
+) -> Result<Vec<jetstream::Message>, AppError> {
+    let mut batch = consumer
+        .batch()
+        .max_messages(settings.size)
+        .expires(Duration::from_millis(settings.wait_ms))
+        .messages()
+        .await
+        .map_err(|err| AppError::Encode(err.into()))?;
+
+    let mut messages = vec![];
+
+    while let Some(message) = batch.next().await {
+        messages.push(message.map_err(AppError::Encode)?);
+    }
+
+    Ok(messages)
+}
+
+// Пачка применяется в одной транзакции, ack — по одному после коммита.
+// Если пачка не прошла, события применяются по одному, чтобы одно плохое не тянуло за собой остальные
+async fn handle_batch<R, F, Fut>(state: &FeedsState, messages: Vec<jetstream::Message>, handler: F)
+where
+    R: for<'a> TryFrom<(&'a jetstream::Message, &'a HeaderMap), Error = AppError> + Clone,
+    F: Fn(Vec<R>) -> Fut,
+    Fut: Future<Output = Result<(), AppError>>,
+{
+    let mut parsed = vec![];
+
+    for message in messages {
+        match parse::<R>(&message) {
+            Ok(req) => parsed.push((message, req)),
+            Err(err) => {
+                if let Err(err) = settle(state, &message, Err(err)).await {
+                    error!("{}", err);
+                }
+            }
+        }
+    }
+
+    if parsed.is_empty() {
+        return;
+    }
+
+    let reqs = parsed.iter().map(|(_, req)| req.clone()).collect();
+
+    if let Err(err) = handler(reqs).await {
+        error!("batch: {}", err);
+
+        for (message, req) in parsed {
+            let res = handler(vec![req]).await;
+
+            if let Err(err) = settle(state, &message, res).await {
+                error!("{}", err);
+            }
+        }
+
+        return;
+    }
+
+    for (message, _) in parsed {
+        if let Err(err) = settle(state, &message, Ok(())).await {
             error!("{}", err);
         }
     }
+}
+
+fn parse<R>(message: &jetstream::Message) -> Result<R, AppError>
+where
+    R: for<'a> TryFrom<(&'a jetstream::Message, &'a HeaderMap), Error = AppError>,
+{
+    let headers = message.headers.as_ref().ok_or(AppError::Unreachable)?;
+
+    R::try_from((message, headers))
+}
+
+// Без ce_time берём время, когда событие принял JetStream, а не момент обработки:
+// при повторной доставке или отставании консьюмера они сильно расходятся
+fn event_time(
+    message: &jetstream::Message,
+    headers: &HeaderMap,
+) -> Result<NaiveDateTime, AppError> {
+    if let Some(time) = headers
+        .get("ce_time")
+        .or_else(|| headers.get(ORIGINAL_PUBLISHED))
+    {
+        return Ok(DateTime::parse_from_rfc3339(time.as_str())?.naive_utc());
+    }
+
+    published_at(message)
+}
+
+fn event_seq(message: &jetstream::Message, headers: &HeaderMap) -> Result<i64, AppError> {
+    let sequence = match headers.get(ORIGINAL_SEQUENCE) {
+        Some(sequence) => sequence
+            .as_str()
+            .parse()
+            .map_err(|_| AppError::Unreachable)?,
+        None => message.info()?.stream_sequence,
+    };
+
+    i64::try_from(sequence).map_err(|_| AppError::Unreachable)
+}
+
+fn published_at(message: &jetstream::Message) -> Result<NaiveDateTime, AppError> {
+    let published = message.info()?.published.unix_timestamp_nanos();
+    let published = i64::try_from(published).map_err(|_| AppError::Unreachable)?;
+
+    Ok(DateTime::from_timestamp_nanos(published).naive_utc())
+}
+
+// Успех — ack, временные ошибки — nak с задержкой, пока не кончились попытки,
+// остальное повторять бессмысленно — в dead letter и term
+async fn settle(
+    state: &FeedsState,
+    message: &jetstream::Message,
+    res: Result<(), AppError>,
+) -> Result<(), AppError> {
+    let FeedsState { mess, settings, .. } = state;
+
+    let Err(err) = res else {
+        message.ack().await?;
+
+        return Ok(());
+    };
+
+    error!("{}", err);
+
+    let delivered = message.info()?.delivered;
+
+    if err.is_transient() && delivered < settings.messaging.max_deliveries {
+        let delay = Duration::from_secs(settings.messaging.nak_delay_secs);
+        message.ack_with(AckKind::Nak(Some(delay))).await?;
+
+        return Ok(());
+    }
+
+    dead_letter(
+        mess,
+        &settings.messaging.dead_letter,
+        message,
+        &err,
+        delivered,
+    )
+    .await?;
+
+    message.ack_with(AckKind::Term).await?;
+
+    Ok(())
+}
+
+async fn dead_letter(
+    mess: &MessState,
+    settings: &DeadLetterSettings,
+    message: &jetstream::Message,
+    err: &AppError,
+    delivered: i64,
+) -> Result<(), AppError> {
+    let mut headers = match &message.headers {
+        Some(source) => copy_headers(source, &[]),
+        None => HeaderMap::new(),
+    };
+    headers.insert(DEAD_LETTER_SUBJECT, message.subject.as_str());
+    headers.insert(DEAD_LETTER_ERROR, format!("{err:?}"));
+    headers.insert(DEAD_LETTER_DELIVERED, delivered.to_string());
+
+    // Повторно упавшее после replay событие уже несёт исходные значения
+    if headers.get(ORIGINAL_SEQUENCE).is_none() {
+        headers.insert(
+            ORIGINAL_PUBLISHED,
+            published_at(message)?
+                .and_utc()
+                .to_rfc3339_opts(SecondsFormat::Nanos, true),
+        );
+        headers.insert(
+            ORIGINAL_SEQUENCE,
+            message.info()?.stream_sequence.to_string(),
+        );
+    }
+
+    mess.js
+        .publish_with_headers(settings.subject.clone(), headers, message.payload.clone())
+        .await?
+        .await?;
 
     Ok(())
 }
 
+// Копия без Nats-Msg-Id: иначе JetStream отбросит повторную публикацию как дубль
+fn copy_headers(source: &HeaderMap, skip: &[&str]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    for (name, values) in source.iter() {
+        if *name == NATS_MESSAGE_ID || skip.contains(&name.as_ref()) {
+            continue;
+        }
+
+        for value in values {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+
+    headers
+}
+
+// Каждый процесс сам читает события outbox, без durable-консьюмера: записи, которые разложили
+// задачи на других репликах, тоже доходят до открытых здесь потоков.
+// Пропущенное, пока процесс переподключался, клиент дочитает из базы по курсору
+pub async fn streaming(state: FeedsState) -> Result<(), Error> {
+    let FeedsState {
+        mess,
+        settings,
+        hub,
+        ..
+    } = &state;
+
+    let mut subscriber = mess.nats.subscribe(settings.outbox.subject.clone()).await?;
+
+    while let Some(message) = subscriber.next().await {
+        match streaming::event(&message) {
+            Ok(Some(streaming::Event::Entry(entry))) => hub.publish(&[entry]),
+            Ok(Some(streaming::Event::MessageTopic(message_topic))) => {
+                hub.publish_message_topic(&message_topic)
+            }
+            Ok(None) => {}
+            Err(err) => error!("{}", err),
+        }
+    }
+
+    Ok(())
+}
+
+// Переносит сообщения из dead letter обратно в исходные сабжекты.
+// Останавливается на последнем сообщении стрима на момент запуска:
+// то, что снова упало во время переноса, ждёт следующего запуска
+pub async fn replay_dead_letters(state: FeedsState) -> Result<(), Error> {
+    let FeedsState { mess, settings, .. } = &state;
+    let settings = &settings.messaging.dead_letter;
+
+    let consumer = mess
+        .js
+        .create_consumer_on_stream(
+            jetstream::consumer::pull::Config {
+                durable_name: Some(settings.consumer.clone()),
+                filter_subject: settings.subject.clone(),
+                ..Default::default()
+            },
+            settings.stream.clone(),
+        )
+        .await?;
+
+    let last_sequence = mess
+        .js
+        .get_stream(&settings.stream)
+        .await?
+        .cached_info()
+        .state
+        .last_sequence;
+
+    let mut replayed = 0;
+
+    'replay: loop {
+        let mut messages = consumer
+            .fetch()
+            .max_messages(REPLAY_BATCH_SIZE)
+            .expires(Duration::from_secs(1))
+            .messages()
+            .await?;
+
+        let mut fetched = 0;
+
+        while let Some(message) = messages.next().await {
+            let message = message.map_err(AppError::Encode)?;
+
+            let sequence = message.info().map_err(AppError::Encode)?.stream_sequence;
+
+            if sequence > last_sequence {
+                message
+                    .ack_with(AckKind::Nak(None))
+                    .await
+                    .map_err(AppError::Encode)?;
+
+                replayed += fetched;
+                break 'replay;
+            }
+
+            replay_dead_letter(mess, &message).await?;
+
+            fetched += 1;
+        }
+
+        if fetched == 0 {
+            break;
+        }
+
+        replayed += fetched;
+    }
+
+    info!("replay: {} dead letters replayed", replayed);
+
+    Ok(())
+}
+
+async fn replay_dead_letter(
+    mess: &MessState,
+    message: &jetstream::Message,
+) -> Result<(), AppError> {
+    let source = message.headers.as_ref().ok_or(AppError::Unreachable)?;
+    let subject = source
+        .get(DEAD_LETTER_SUBJECT)
+        .ok_or(AppError::Unreachable)?
+        .to_string();
+
+    let headers = copy_headers(source, &DEAD_LETTER_HEADERS);
+
+    mess.js
+        .publish_with_headers(subject, headers, message.payload.clone())
+        .await?
+        .await?;
+
+    message.ack().await?;
+
+    Ok(())
+}
+
+mod streaming {
+    use async_nats::Message;
+    use bzd_flux_api::events::{EntryCreated, TopicMessageCreated};
+    use chrono::DateTime;
+    use prost::Message as _;
+    use sea_orm::ActiveEnum as _;
+
+    use crate::app::{
+        error::AppError,
+        feeds::repo::{EntryModel, MessageTopicModel, outbox::Type},
+    };
+
+    pub enum Event {
+        Entry(EntryModel),
+        // Сообщение крупного топика: записи подписчикам собирает hub
+        MessageTopic(MessageTopicModel),
+    }
+
+    // Остальные события в сабжекте потокам не нужны
+    pub fn event(message: &Message) -> Result<Option<Event>, AppError> {
+        let headers = message.headers.as_ref().ok_or(AppError::Unreachable)?;
+        let tp = headers.get("ce_type").ok_or(AppError::Unreachable)?;
+
+        if tp.as_str() == Type::EntryCreated.to_value() {
+            let event = EntryCreated::decode(message.payload.clone())?;
+
+            return Ok(Some(Event::Entry(EntryModel {
+                entry_id: event.entry_id().parse()?,
+                ..EntryModel::new(
+                    event.user_id().parse()?,
+                    event.message_id().parse()?,
+                    vec![],
+                )
+            })));
+        }
+
+        if tp.as_str() == Type::TopicMessageCreated.to_value() {
+            let event = TopicMessageCreated::decode(message.payload.clone())?;
+
+            let published_at = event
+                .published_at
+                .and_then(|it| DateTime::from_timestamp(it.seconds, u32::try_from(it.nanos).ok()?))
+                .ok_or(AppError::Unreachable)?
+                .naive_utc();
+
+            return Ok(Some(Event::MessageTopic(MessageTopicModel {
+                entry_id: event.entry_id().parse()?,
+                fanout_on_read: true,
+                ..MessageTopicModel::new(
+                    event.message_topic_id().parse()?,
+                    event.message_id().parse()?,
+                    event.topic_id().parse()?,
+                    published_at,
+                )
+            })));
+        }
+
+        Ok(None)
+    }
+}
+
 mod messages_topics {
     use std::str::FromStr as _;
 
@@ -33,9 +453,10 @@ mod messages_topics {
     use crate::app::{
         error::AppError,
         feeds::{
+            messaging::event_time,
             service::{
                 self,
-                handle_message_topic::{Request, Type},
+                handle_messages_topics::{Request, Type},
             },
             settings::FeedsSettings,
             state::FeedsState,
@@ -60,14 +481,10 @@ mod messages_topics {
             .await?)
     }
 
-    pub async fn handler(state: &FeedsState, message: jetstream::Message) -> Result<(), AppError> {
+    pub async fn handler(state: &FeedsState, reqs: Vec<Request>) -> Result<(), AppError> {
         let FeedsState { db, .. } = state;
 
-        let headers = message.headers.as_ref().ok_or(AppError::Unreachable)?;
-
-        service::handle_message_topic(&db.conn, (&message, headers).try_into()?).await?;
-
-        message.ack().await?;
+        service::handle_messages_topics(&db.conn, reqs).await?;
 
         Ok(())
     }
@@ -79,13 +496,21 @@ mod messages_topics {
             (message, headers): (&jetstream::Message, &HeaderMap),
         ) -> Result<Self, Self::Error> {
             let tp = headers.get("ce_type").ok_or(AppError::Unreachable)?;
+            let published_at = event_time(message, headers)?;
             let message = bzd_messages_api::events::MessageTopic::decode(message.payload.clone())?;
+            let message_topic_id = message.message_topic_id().parse()?;
+            let event_id = match headers.get("ce_id") {
+                Some(id) => id.to_string(),
+                None => format!("{}:{}", tp.as_str(), message_topic_id),
+            };
 
             Ok(Self {
-                tp: Type::from_str(&tp.to_string())?,
-                message_topic_id: message.message_topic_id().parse()?,
+                event_id,
+                tp: Type::from_str(tp.as_str())?,
+                message_topic_id,
                 topic_id: message.topic_id().parse()?,
                 message_id: message.message_id().parse()?,
+                published_at,
             })
         }
     }
@@ -93,15 +518,12 @@ mod messages_topics {
 
 pub async fn topics_users(state: FeedsState) -> Result<(), Error> {
     let consumer = topics_users::consumer(&state.mess, &state.settings).await?;
-    let mut messages = consumer.messages().await?;
 
-    while let Some(message) = messages.next().await {
-        if let Err(err) = topics_users::handler(&state, message?).await {
-            error!("{}", err);
-        }
-    }
+    loop {
+        let messages = fetch(&consumer, &state.settings.messaging.batch).await?;
 
-    Ok(())
+        handle_batch(&state, messages, |reqs| topics_users::handler(&state, reqs)).await;
+    }
 }
 
 mod topics_users {
@@ -121,7 +543,8 @@ mod topics_users {
     use crate::app::{
         error::AppError,
         feeds::{
-            service::{self, handle_topic_user::Request},
+            messaging::{event_seq, event_time},
+            service::{self, handle_topics_users::Request},
             settings::FeedsSettings,
             state::FeedsState,
         },
@@ -145,14 +568,10 @@ mod topics_users {
             .await?)
     }
 
-    pub async fn handler(state: &FeedsState, message: jetstream::Message) -> Result<(), AppError> {
-        let FeedsState { db, .. } = state;
+    pub async fn handler(state: &FeedsState, reqs: Vec<Request>) -> Result<(), AppError> {
+        let FeedsState { db, settings, .. } = state;
 
-        let headers = message.headers.as_ref().ok_or(AppError::Unreachable)?;
-
-        service::handle_topic_user(&db.conn, (&message, headers).try_into()?).await?;
-
-        message.ack().await?;
+        service::handle_topics_users(&db.conn, settings, reqs).await?;
 
         Ok(())
     }
@@ -164,14 +583,18 @@ mod topics_users {
             (message, headers): (&jetstream::Message, &HeaderMap),
         ) -> Result<Self, Self::Error> {
             let tp = headers.get("ce_type").ok_or(AppError::Unreachable)?;
+            let event_at = event_time(message, headers)?;
+            let event_seq = event_seq(message, headers)?;
 
             let message = bzd_messages_api::events::TopicUser::decode(message.payload.clone())?;
 
             Ok(Self {
-                tp: Type::from_str(&tp.to_string())?,
+                tp: Type::from_str(tp.as_str())?,
                 topic_user_id: message.topic_user_id().parse()?,
                 topic_id: message.topic_id().parse()?,
                 user_id: message.user_id().parse()?,
+                event_at,
+                event_seq,
             })
         }
     }
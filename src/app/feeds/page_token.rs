@@ -0,0 +1,137 @@
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac as _};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::app::{
+    error::AppError,
+    feeds::service::get_user_entries::{Direction, Filters},
+};
+
+const VERSION: &str = "v1";
+
+// Позиция в ленте конкретного пользователя, выдаётся клиенту как непрозрачная строка
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageToken {
+    pub user_id: Uuid,
+    pub direction: Direction,
+    pub entry_id: Uuid,
+    pub filters: Filters,
+}
+
+// Формат: "<версия>.<base64 json>.<base64 hmac-sha256 от версии и json>"
+pub fn encode(key: &str, token: &PageToken) -> Result<String, AppError> {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(token)?);
+    let signature = URL_SAFE_NO_PAD.encode(mac(key, VERSION, &payload)?.finalize().into_bytes());
+
+    Ok(format!("{VERSION}.{payload}.{signature}"))
+}
+
+pub fn decode(key: &str, token: &str) -> Result<PageToken, AppError> {
+    let mut parts = token.split('.');
+
+    let (Some(version), Some(payload), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(AppError::InvalidPageToken);
+    };
+
+    if version != VERSION {
+        return Err(AppError::InvalidPageToken);
+    }
+
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| AppError::InvalidPageToken)?;
+
+    mac(key, version, payload)?
+        .verify_slice(&signature)
+        .map_err(|_| AppError::InvalidPageToken)?;
+
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| AppError::InvalidPageToken)?;
+
+    serde_json::from_slice(&payload).map_err(|_| AppError::InvalidPageToken)
+}
+
+fn mac(key: &str, version: &str, payload: &str) -> Result<Hmac<Sha256>, AppError> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).map_err(|_| AppError::Unreachable)?;
+    mac.update(version.as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+
+    Ok(mac)
+}
+
+#[cfg(test)]
+mod tests {
+    use bzd_lib::error::Error;
+    use uuid::Uuid;
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            page_token::{self, PageToken},
+            service::get_user_entries::{Direction, Filters},
+        },
+    };
+
+    fn stub() -> PageToken {
+        PageToken {
+            user_id: Uuid::now_v7(),
+            direction: Direction::Older,
+            entry_id: Uuid::now_v7(),
+            filters: Filters::default(),
+        }
+    }
+
+    #[test]
+    fn test_ok_encode_and_decode() -> Result<(), Error> {
+        let token = stub();
+
+        let encoded = page_token::encode("key", &token)?;
+
+        assert!(!encoded.contains(&token.entry_id.to_string()));
+        assert_eq!(page_token::decode("key", &encoded)?, token);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_err_decode_with_other_key() -> Result<(), Error> {
+        let encoded = page_token::encode("key", &stub())?;
+
+        assert!(matches!(
+            page_token::decode("other", &encoded),
+            Err(AppError::InvalidPageToken)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_err_decode_tampered() -> Result<(), Error> {
+        let encoded = page_token::encode("key", &stub())?;
+        let (_, signature) = encoded.rsplit_once('.').ok_or(AppError::Unreachable)?;
+
+        let forged = page_token::encode("other", &stub())?;
+        let (forged, _) = forged.rsplit_once('.').ok_or(AppError::Unreachable)?;
+
+        for token in [
+            format!("{forged}.{signature}"),
+            encoded.replacen("v1.", "v2.", 1),
+            encoded.replace('.', ""),
+            String::new(),
+        ] {
+            assert!(matches!(
+                page_token::decode("key", &token),
+                Err(AppError::InvalidPageToken)
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -1,66 +1,451 @@
+use std::sync::Arc;
 use std::time::Duration;
 
-use chrono::Utc;
-use sea_orm::{DbConn, TransactionTrait};
-use tokio_stream::StreamExt;
-use tokio_stream::wrappers::IntervalStream;
+use async_nats::HeaderMap;
+use chrono::{TimeDelta, Utc};
+use sea_orm::{ActiveEnum as _, DbConn, TransactionTrait, sqlx::postgres::PgListener};
+use tokio::select;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
 use tracing::error;
+use uuid::Uuid;
 
 use crate::app::error::AppError;
-use crate::app::feeds::settings::ProcessingSettings;
+use crate::app::feeds::settings::{
+    FeedsSettings, InboxSettings, OutboxSettings, ProcessingSettings,
+};
 use crate::app::feeds::state::FeedsState;
 use crate::app::feeds::{repo, service};
+use crate::app::mess::MessState;
+
+const LAST_ERROR_LEN: usize = 1000;
 
 pub async fn tasks(state: FeedsState) -> Result<(), AppError> {
     let FeedsState { settings, db, .. } = state;
 
-    let mut interval = tokio::time::interval(Duration::from_secs(3));
+    let mut listener = listen(&db.conn).await?;
+
+    // Интервал остаётся страховкой: backoff, протухшие локи, потерянные NOTIFY
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(settings.processing.interval_secs));
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
-    let mut tss = IntervalStream::new(interval);
+    loop {
+        select! {
+            _ = interval.tick() => {}
+            notification = listener.recv() => {
+                if let Err(err) = notification {
+                    error!("{}", err);
 
-    while let Some(_) = tss.next().await {
-        if let Err(err) = process_tasks(&db.conn, &settings.processing).await {
+                    // Без паузы recv на недоступной базе сразу вернёт ошибку снова
+                    tokio::time::sleep(Duration::from_secs(settings.processing.interval_secs))
+                        .await;
+
+                    match listen(&db.conn).await {
+                        Ok(it) => listener = it,
+                        Err(err) => error!("{}", err),
+                    }
+                }
+            }
+        }
+
+        if let Err(err) = process_tasks(&db.conn, &settings).await {
             error!("{}", err);
         }
     }
+}
 
-    Ok(())
+async fn listen(db: &DbConn) -> Result<PgListener, AppError> {
+    let mut listener = PgListener::connect_with(db.get_postgres_connection_pool()).await?;
+    listener.listen(repo::TASKS_CHANNEL).await?;
+
+    Ok(listener)
+}
+
+pub async fn outbox(state: FeedsState) -> Result<(), AppError> {
+    let FeedsState {
+        settings, db, mess, ..
+    } = state;
+
+    let mut interval = tokio::time::interval(Duration::from_millis(settings.outbox.interval_ms));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        // Полная пачка — скорее всего есть ещё, не ждём следующего тика
+        loop {
+            match relay_outbox_events(&db.conn, &mess, &settings.outbox).await {
+                Ok(count) if count == settings.outbox.batch_size => {}
+                Ok(_) => break,
+                Err(err) => {
+                    error!("{}", err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Инбокс нужен только на время возможной повторной доставки, дальше записи только растят таблицу
+pub async fn inbox(state: FeedsState) -> Result<(), AppError> {
+    let FeedsState { settings, db, .. } = state;
+
+    let mut interval = tokio::time::interval(Duration::from_secs(settings.inbox.interval_secs));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        loop {
+            match purge_inbox_events(&db.conn, &settings.inbox).await {
+                Ok(count) if count == settings.inbox.batch_size => {}
+                Ok(_) => break,
+                Err(err) => {
+                    error!("{}", err);
+                    break;
+                }
+            }
+        }
+    }
 }
 
-async fn process_tasks(db: &DbConn, settings: &ProcessingSettings) -> Result<(), AppError> {
+async fn purge_inbox_events(db: &DbConn, settings: &InboxSettings) -> Result<u64, AppError> {
+    let ttl =
+        TimeDelta::seconds(i64::try_from(settings.ttl_secs).map_err(|_| AppError::Unreachable)?);
+    let before = Utc::now().naive_utc() - ttl;
+
+    repo::delete_expired_inbox_events(db, before, settings.batch_size).await
+}
+
+// Строки удаляются в той же транзакции после подтверждения публикации,
+// поэтому доставка at-least-once: получатели дедуплицируют по ce_id
+async fn relay_outbox_events(
+    db: &DbConn,
+    mess: &MessState,
+    settings: &OutboxSettings,
+) -> Result<u64, AppError> {
     let tx = db.begin().await?;
 
-    let tasks = repo::get_earliest_tasks(&tx, settings.batch_size).await?;
+    let events = repo::get_earliest_outbox_events(&tx, settings.batch_size).await?;
+
+    if events.is_empty() {
+        return Ok(0);
+    }
+
+    let mut acks = Vec::with_capacity(events.len());
+
+    for event in &events {
+        let ack = mess
+            .js
+            .publish_with_headers(
+                settings.subject.clone(),
+                outbox_headers(settings, event),
+                event.payload.clone().into(),
+            )
+            .await?;
+
+        acks.push(ack);
+    }
+
+    for ack in acks {
+        ack.await?;
+    }
+
+    let event_ids: Vec<_> = events.iter().map(|it| it.event_id).collect();
+    let count = u64::try_from(event_ids.len()).map_err(|_| AppError::Unreachable)?;
+
+    repo::delete_outbox_events(&tx, event_ids).await?;
+
+    tx.commit().await?;
+
+    Ok(count)
+}
+
+fn outbox_headers(settings: &OutboxSettings, event: &repo::OutboxModel) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("ce_specversion", "1.0");
+    headers.insert("ce_id", event.event_id.to_string());
+    headers.insert("ce_source", settings.source.as_str());
+    headers.insert("ce_type", event.tp.to_value());
+    headers.insert("ce_time", event.created_at.and_utc().to_rfc3339());
+    headers.insert("content-type", "application/protobuf");
+
+    headers
+}
+
+async fn process_tasks(db: &Arc<DbConn>, settings: &FeedsSettings) -> Result<(), AppError> {
+    let tx = db.begin().await?;
+
+    let tasks = repo::get_earliest_tasks(
+        &tx,
+        settings.processing.batch_size,
+        settings.processing.lock_secs,
+    )
+    .await?;
     let task_ids = tasks.iter().map(|it| it.task_id).collect();
     let locked_at = Utc::now().naive_utc();
+    let locked_by = Uuid::now_v7();
 
-    if tasks.len() > 0 {
-        repo::mark_tasks_as_locked(&tx, task_ids, locked_at).await?;
+    if !tasks.is_empty() {
+        repo::mark_tasks_as_locked(&tx, task_ids, locked_at, locked_by).await?;
     }
 
     tx.commit().await?;
 
-    // TODO: нужно сделать параллельно
+    let tasks = tasks.into_iter().map(|mut it| {
+        it.locked_at = Some(locked_at);
+        it.locked_by = Some(locked_by);
+        it
+    });
+
+    let semaphore = Arc::new(Semaphore::new(settings.processing.concurrency));
+    let mut set = JoinSet::new();
+
     for task in tasks {
-        process_task(db, task).await?;
+        let semaphore = Arc::clone(&semaphore);
+        let db = Arc::clone(db);
+        let settings = settings.clone();
+
+        set.spawn(async move {
+            match process_locked_task(&db, &settings, &semaphore, task.clone()).await {
+                Ok(()) => {}
+                Err(AppError::LockLost) => error!("task {}: lock lost", task.task_id),
+                Err(err) => {
+                    error!("task {}: {}", task.task_id, err);
+
+                    if let Err(err) = fail_task(&db, &settings.processing, task, err).await {
+                        error!("{}", err);
+                    }
+                }
+            }
+        });
+    }
+
+    while let Some(res) = set.join_next().await {
+        if let Err(err) = res {
+            error!("{}", err);
+        }
     }
 
     Ok(())
 }
 
-async fn process_task(db: &DbConn, task: repo::task::Model) -> Result<(), AppError> {
+// Лок продлевается с момента захвата, в том числе пока задача ждёт свободного слота
+async fn process_locked_task(
+    db: &DbConn,
+    settings: &FeedsSettings,
+    semaphore: &Semaphore,
+    task: repo::task::Model,
+) -> Result<(), AppError> {
+    select! {
+        res = async {
+            let _permit = semaphore.acquire().await.map_err(|_| AppError::Unreachable)?;
+
+            process_task(db, settings, task.clone()).await
+        } => res,
+        err = keep_task_locked(db, &settings.processing, &task) => Err(err),
+    }
+}
+
+// Продлевает лок, пока задача выполняется; возвращается только если лок потерян.
+// Разовая ошибка продления не страшна, пока не истёк срок последнего успешного продления
+async fn keep_task_locked(
+    db: &DbConn,
+    settings: &ProcessingSettings,
+    task: &repo::task::Model,
+) -> AppError {
+    let lock = Duration::from_secs(settings.lock_secs);
+    let mut renewed_at = Instant::now();
+
+    let mut interval = tokio::time::interval(Duration::from_secs((settings.lock_secs / 2).max(1)));
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        match repo::renew_task_lock(db, task, Utc::now().naive_utc()).await {
+            Ok(()) => renewed_at = Instant::now(),
+            Err(AppError::LockLost) => return AppError::LockLost,
+            Err(err) => {
+                error!("task {}: {}", task.task_id, err);
+
+                if renewed_at.elapsed() >= lock {
+                    return AppError::LockLost;
+                }
+            }
+        }
+    }
+}
+
+async fn fail_task(
+    db: &DbConn,
+    settings: &ProcessingSettings,
+    task: repo::task::Model,
+    err: AppError,
+) -> Result<(), AppError> {
+    repo::fail_task(
+        db,
+        &task,
+        last_error(&err),
+        settings.max_attempts,
+        settings.backoff_base_secs,
+        settings.backoff_max_secs,
+    )
+    .await
+}
+
+// Текст ошибки вместе с причинами, обрезанный, чтобы не раздувать строку задачи
+fn last_error(err: &AppError) -> String {
+    let mut message = err.to_string();
+    let mut source = std::error::Error::source(err);
+
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+
+    message.chars().take(LAST_ERROR_LEN).collect()
+}
+
+async fn process_task(
+    db: &DbConn,
+    settings: &FeedsSettings,
+    task: repo::task::Model,
+) -> Result<(), AppError> {
     match task.payload.clone() {
         repo::task::Payload::CreateMessageTopic(payload) => {
             let last_topic_user_id =
-                service::create_entries_from_message_topic(db, payload.into()).await?;
+                service::create_entries_from_message_topic(db, settings, payload.into()).await?;
 
             match last_topic_user_id {
                 Some(last_topic_user_id) => repo::unlock_task(db, task, last_topic_user_id).await?,
                 None => repo::delete_task(db, task).await?,
             }
         }
+        repo::task::Payload::DeleteMessageTopic(payload) => {
+            let last_entry_id =
+                service::delete_entries_from_message_topic(db, payload.into()).await?;
+
+            match last_entry_id {
+                Some(last_entry_id) => repo::unlock_task(db, task, last_entry_id).await?,
+                None => repo::delete_task(db, task).await?,
+            }
+        }
+        repo::task::Payload::PurgeTopicUser(payload) => {
+            let last_entry_id = service::purge_entries_from_topic_user(db, payload.into()).await?;
+
+            match last_entry_id {
+                Some(last_entry_id) => repo::unlock_task(db, task, last_entry_id).await?,
+                None => repo::delete_task(db, task).await?,
+            }
+        }
+        repo::task::Payload::BackfillTopicUser(payload) => {
+            service::create_entries_from_topic_user(db, payload.into()).await?;
+
+            repo::delete_task(db, task).await?;
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use bzd_lib::error::Error;
+    use sea_orm::{DatabaseBackend, DbErr, MockDatabase, MockExecResult};
+    use uuid::Uuid;
+
+    use crate::app::{
+        error::AppError,
+        feeds::{
+            processing::{LAST_ERROR_LEN, fail_task, purge_inbox_events},
+            repo::{
+                TaskModel,
+                task::{Payload, PurgeTopicUser},
+            },
+            settings::FeedsSettings,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_ok_fail_task() -> Result<(), Error> {
+        let settings = FeedsSettings::stub().processing;
+        let task = TaskModel {
+            locked_by: Some(Uuid::now_v7()),
+            ..TaskModel::new(Payload::PurgeTopicUser(PurgeTopicUser {
+                topic_user_id: Uuid::now_v7(),
+                user_id: Uuid::now_v7(),
+                last_entry_id: None,
+            }))
+        };
+        let err = AppError::Db(DbErr::Custom("x".repeat(2 * LAST_ERROR_LEN)));
+
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection();
+
+        fail_task(&db, &settings, task, err).await?;
+
+        let log = db.into_transaction_log();
+        let update = &log[0].statements()[0];
+
+        assert!(update.sql.contains(r#""attempts" = "attempts" + $1"#));
+        assert!(
+            update
+                .sql
+                .contains("case when tasks.attempts + 1 >= $2 then 'dead' else 'active' end")
+        );
+        assert!(
+            update
+                .sql
+                .contains("least($4 * power(2, tasks.attempts), $5) * interval '1 second'")
+        );
+
+        let last_error = update
+            .values
+            .as_ref()
+            .and_then(|it| {
+                it.0.iter().find_map(|it| match it {
+                    sea_orm::Value::String(Some(it)) if it.starts_with("DB: ") => Some(it.clone()),
+                    _ => None,
+                })
+            })
+            .ok_or(AppError::Unreachable)?;
+
+        assert_eq!(last_error.chars().count(), LAST_ERROR_LEN);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ok_purge_inbox_events() -> Result<(), Error> {
+        let settings = FeedsSettings::stub().inbox;
+
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 7,
+            }])
+            .into_connection();
+
+        let count = purge_inbox_events(&db, &settings).await?;
+
+        assert_eq!(count, 7);
+
+        let log = db.into_transaction_log();
+        let delete = &log[0].statements()[0];
+
+        assert_eq!(
+            delete.sql,
+            r#"DELETE FROM "inbox" WHERE "inbox"."event_id" IN (SELECT "event_id" FROM "inbox" WHERE "inbox"."created_at" < $1 LIMIT $2)"#
+        );
+
+        Ok(())
+    }
+}
@@ -1,55 +1,312 @@
+use std::collections::HashSet;
+
 use chrono::{Duration, NaiveDateTime, Utc};
 use sea_orm::{
-    ActiveModelTrait as _,
     ActiveValue::Set,
-    ColumnTrait, Condition, ConnectionTrait, EntityTrait, IntoActiveModel as _, ModelTrait as _,
-    QueryFilter as _, QueryOrder, QuerySelect, QueryTrait as _,
+    ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait, IntoActiveModel as _,
+    QueryFilter as _, QueryOrder, QuerySelect, QueryTrait as _, Statement, Value,
     prelude::Expr,
-    sea_query::{LockBehavior, LockType, OnConflict},
+    sea_query::{LockBehavior, LockType, OnConflict, Query, SimpleExpr},
 };
 use uuid::Uuid;
 
 use crate::app::error::AppError;
 
 pub mod entry;
+pub mod inbox;
+pub mod message_topic;
+pub mod outbox;
+pub mod read_marker;
 pub mod task;
+pub mod topic;
 pub mod topic_user;
 
 pub type EntryModel = entry::Model;
+pub type InboxModel = inbox::Model;
+pub type MessageTopicModel = message_topic::Model;
+pub type OutboxModel = outbox::Model;
+pub type ReadMarkerModel = read_marker::Model;
 pub type TaskModel = task::Model;
+pub type TopicModel = topic::Model;
 pub type TopicUserModel = topic_user::Model;
 
-pub async fn create_task<T: ConnectionTrait>(
+pub const TASKS_CHANNEL: &str = "bzd_flux_tasks";
+
+// Один NOTIFY на всю пачку задач
+pub async fn create_tasks<T: ConnectionTrait>(
     db: &T,
-    model: TaskModel,
-) -> Result<TaskModel, AppError> {
-    let task = model.into_active_model().insert(db).await?;
+    models: Vec<TaskModel>,
+) -> Result<(), AppError> {
+    if models.is_empty() {
+        return Ok(());
+    }
+
+    task::Entity::insert_many(models.into_iter().map(|it| it.into_active_model()))
+        .exec_without_returning(db)
+        .await?;
+
+    notify_tasks(db).await?;
+
+    Ok(())
+}
+
+// Внутри транзакции Postgres доставит NOTIFY только после COMMIT
+async fn notify_tasks<T: ConnectionTrait>(db: &T) -> Result<(), AppError> {
+    db.execute(Statement::from_string(
+        db.get_database_backend(),
+        format!("NOTIFY {TASKS_CHANNEL}"),
+    ))
+    .await?;
 
-    Ok(task)
+    Ok(())
 }
 
-pub async fn upsert_topic_user<T: ConnectionTrait>(
+// Удаление пишется как tombstone, чтобы опоздавший Created его не воскресил.
+// Возвращает только применённые записи: более старые события пропускаются
+pub async fn upsert_topics_users<T: ConnectionTrait>(
     db: &T,
-    model: TopicUserModel,
-) -> Result<(), AppError> {
-    topic_user::Entity::insert(model.into_active_model())
+    models: Vec<TopicUserModel>,
+) -> Result<Vec<TopicUserModel>, AppError> {
+    // В одном INSERT ... ON CONFLICT нельзя дважды обновить одну строку, оставляем последнее событие
+    let mut topics_users: Vec<TopicUserModel> = Vec::with_capacity(models.len());
+
+    for model in models {
+        match topics_users
+            .iter_mut()
+            .find(|it| it.topic_user_id == model.topic_user_id)
+        {
+            Some(topic_user) => {
+                if (topic_user.event_at, topic_user.event_seq) < (model.event_at, model.event_seq) {
+                    *topic_user = model;
+                }
+            }
+            None => topics_users.push(model),
+        }
+    }
+
+    if topics_users.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let topics_users = topic_user::Entity::insert_many(
+        topics_users.into_iter().map(|it| it.into_active_model()),
+    )
         .on_conflict(
             OnConflict::column(topic_user::Column::TopicUserId)
+                .update_columns([
+                    topic_user::Column::EventAt,
+                    topic_user::Column::EventSeq,
+                    topic_user::Column::DeletedAt,
+                    topic_user::Column::UpdatedAt,
+                ])
+                .action_and_where(Expr::cust(
+                    "(topics_users.event_at, topics_users.event_seq) < (excluded.event_at, excluded.event_seq)",
+                ))
+                .to_owned(),
+        )
+        .exec_with_returning_many(db)
+        .await?;
+
+    Ok(topics_users)
+}
+
+// Возвращает id только новых событий, уже обработанные пропускаются
+pub async fn create_inbox_events<T: ConnectionTrait>(
+    db: &T,
+    models: Vec<InboxModel>,
+) -> Result<HashSet<String>, AppError> {
+    if models.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let events = inbox::Entity::insert_many(models.into_iter().map(|it| it.into_active_model()))
+        .on_conflict(
+            OnConflict::column(inbox::Column::EventId)
                 .do_nothing()
                 .to_owned(),
         )
-        .do_nothing()
+        .exec_with_returning_many(db)
+        .await?;
+
+    Ok(events.into_iter().map(|it| it.event_id).collect())
+}
+
+// Удаляет пачку записей старше before, возвращает число удалённых
+pub async fn delete_expired_inbox_events<T: ConnectionTrait>(
+    db: &T,
+    before: NaiveDateTime,
+    limit: u64,
+) -> Result<u64, AppError> {
+    let res = inbox::Entity::delete_many()
+        .filter(
+            inbox::Column::EventId.in_subquery(
+                Query::select()
+                    .column(inbox::Column::EventId)
+                    .from(inbox::Entity)
+                    .and_where(inbox::Column::CreatedAt.lt(before))
+                    .limit(limit)
+                    .to_owned(),
+            ),
+        )
         .exec(db)
         .await?;
 
+    Ok(res.rows_affected)
+}
+
+pub async fn upsert_messages_topics<T: ConnectionTrait>(
+    db: &T,
+    models: Vec<MessageTopicModel>,
+) -> Result<(), AppError> {
+    if models.is_empty() {
+        return Ok(());
+    }
+
+    message_topic::Entity::insert_many(models.into_iter().map(|it| it.into_active_model()))
+        .on_conflict(
+            OnConflict::column(message_topic::Column::MessageTopicId)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+
     Ok(())
 }
 
-pub async fn delete_topic_user<T: ConnectionTrait>(
+pub async fn delete_messages_topics<T: ConnectionTrait>(
     db: &T,
-    model: TopicUserModel,
+    message_topic_ids: Vec<Uuid>,
 ) -> Result<(), AppError> {
-    model.delete(db).await?;
+    if message_topic_ids.is_empty() {
+        return Ok(());
+    }
+
+    message_topic::Entity::delete_many()
+        .filter(message_topic::Column::MessageTopicId.is_in(message_topic_ids))
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+// Блокирует строку до конца транзакции: удаление сообщения из топика дождётся текущей пачки рассылки
+pub async fn has_message_topic<T: ConnectionTrait>(
+    db: &T,
+    message_id: Uuid,
+    topic_id: Uuid,
+) -> Result<bool, AppError> {
+    let message_topic = message_topic::Entity::find()
+        .filter(message_topic::Column::MessageId.eq(message_id))
+        .filter(message_topic::Column::TopicId.eq(topic_id))
+        .lock_shared()
+        .one(db)
+        .await?;
+
+    Ok(message_topic.is_some())
+}
+
+// Вместе с сообщениями крупных топиков: при чтении они подмешиваются только с момента подписки,
+// и без записей из бэкфилла лента нового подписчика была бы пустой
+pub async fn get_latest_messages_topics_by_topic_id<T: ConnectionTrait>(
+    db: &T,
+    topic_id: Uuid,
+    limit: u64,
+) -> Result<Vec<MessageTopicModel>, AppError> {
+    let messages_topics = message_topic::Entity::find()
+        .filter(message_topic::Column::TopicId.eq(topic_id))
+        .order_by_desc(message_topic::Column::PublishedAt)
+        .limit(limit)
+        .all(db)
+        .await?;
+
+    Ok(messages_topics)
+}
+
+// Возвращает только впервые отмеченные строки, повтор задачи ничего не вернёт
+pub async fn mark_message_topic_as_fanout_on_read<T: ConnectionTrait>(
+    db: &T,
+    message_id: Uuid,
+    topic_id: Uuid,
+) -> Result<Vec<MessageTopicModel>, AppError> {
+    let messages_topics = message_topic::Entity::update_many()
+        .col_expr(message_topic::Column::FanoutOnRead, Expr::value(true))
+        .col_expr(
+            message_topic::Column::UpdatedAt,
+            Expr::value(Utc::now().naive_utc()),
+        )
+        .filter(message_topic::Column::MessageId.eq(message_id))
+        .filter(message_topic::Column::TopicId.eq(topic_id))
+        .filter(message_topic::Column::FanoutOnRead.eq(false))
+        .exec_with_returning(db)
+        .await?;
+
+    Ok(messages_topics)
+}
+
+pub async fn get_messages_topics_fanout_on_read<T: ConnectionTrait>(
+    db: &T,
+    topics_users: &[TopicUserModel],
+    cursor_entry_id: Option<Uuid>,
+    limit: u64,
+) -> Result<Vec<MessageTopicModel>, AppError> {
+    let messages_topics = message_topic::Entity::find()
+        .filter(published_since_subscribed(topics_users))
+        .filter(message_topic::Column::FanoutOnRead.eq(true))
+        .apply_if(cursor_entry_id, |query, v| {
+            query.filter(message_topic::Column::EntryId.lte(v))
+        })
+        .order_by_desc(message_topic::Column::EntryId)
+        .limit(limit)
+        .all(db)
+        .await?;
+
+    Ok(messages_topics)
+}
+
+pub async fn get_messages_topics_fanout_on_read_after_entry_id<T: ConnectionTrait>(
+    db: &T,
+    topics_users: &[TopicUserModel],
+    cursor_entry_id: Option<Uuid>,
+    limit: u64,
+) -> Result<Vec<MessageTopicModel>, AppError> {
+    let messages_topics = message_topic::Entity::find()
+        .filter(published_since_subscribed(topics_users))
+        .filter(message_topic::Column::FanoutOnRead.eq(true))
+        .apply_if(cursor_entry_id, |query, v| {
+            query.filter(message_topic::Column::EntryId.gt(v))
+        })
+        .order_by_asc(message_topic::Column::EntryId)
+        .limit(limit)
+        .all(db)
+        .await?;
+
+    Ok(messages_topics)
+}
+
+pub async fn get_topic<T: ConnectionTrait>(
+    db: &T,
+    topic_id: Uuid,
+) -> Result<Option<TopicModel>, AppError> {
+    let topic = topic::Entity::find_by_id(topic_id).one(db).await?;
+
+    Ok(topic)
+}
+
+// Режим только включается: раз ставший крупным топик не возвращается к рассылке при записи
+pub async fn upsert_topic<T: ConnectionTrait>(db: &T, model: TopicModel) -> Result<(), AppError> {
+    topic::Entity::insert(model.into_active_model())
+        .on_conflict(
+            OnConflict::column(topic::Column::TopicId)
+                .value(
+                    topic::Column::FanoutOnRead,
+                    Expr::cust("topics.fanout_on_read or excluded.fanout_on_read"),
+                )
+                .update_columns([topic::Column::CheckedAt, topic::Column::UpdatedAt])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
 
     Ok(())
 }
@@ -57,15 +314,25 @@ pub async fn delete_topic_user<T: ConnectionTrait>(
 pub async fn get_earliest_tasks<T: ConnectionTrait>(
     db: &T,
     limit: u64,
+    lock_secs: u64,
 ) -> Result<Vec<TaskModel>, AppError> {
-    let locked_at = Utc::now() - Duration::try_seconds(5).ok_or(AppError::Unreachable)?;
+    let lock_secs = i64::try_from(lock_secs).map_err(|_| AppError::Unreachable)?;
+    let locked_at = Utc::now() - Duration::try_seconds(lock_secs).ok_or(AppError::Unreachable)?;
+
+    let now = Utc::now().naive_utc();
 
     let tasks = task::Entity::find()
+        .filter(task::Column::Status.eq(task::Status::Active))
         .filter(
             Condition::any()
                 .add(task::Column::LockedAt.is_null())
                 .add(task::Column::LockedAt.lt(locked_at)),
         )
+        .filter(
+            Condition::any()
+                .add(task::Column::NextRunAt.is_null())
+                .add(task::Column::NextRunAt.lte(now)),
+        )
         .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
         .order_by_asc(task::Column::TaskId)
         .limit(limit)
@@ -79,9 +346,11 @@ pub async fn mark_tasks_as_locked<T: ConnectionTrait>(
     db: &T,
     task_ids: Vec<Uuid>,
     locked_at: NaiveDateTime,
+    locked_by: Uuid,
 ) -> Result<(), AppError> {
     task::Entity::update_many()
         .col_expr(task::Column::LockedAt, Expr::value(locked_at))
+        .col_expr(task::Column::LockedBy, Expr::value(locked_by))
         .col_expr(task::Column::UpdatedAt, Expr::value(Utc::now().naive_utc()))
         .filter(task::Column::TaskId.is_in(task_ids))
         .exec(db)
@@ -90,30 +359,192 @@ pub async fn mark_tasks_as_locked<T: ConnectionTrait>(
     Ok(())
 }
 
+pub async fn renew_task_lock<T: ConnectionTrait>(
+    db: &T,
+    model: &TaskModel,
+    locked_at: NaiveDateTime,
+) -> Result<(), AppError> {
+    let locked_by = model.locked_by.ok_or(AppError::LockLost)?;
+
+    let res = task::Entity::update_many()
+        .col_expr(task::Column::LockedAt, Expr::value(locked_at))
+        .filter(task::Column::TaskId.eq(model.task_id))
+        .filter(task::Column::LockedBy.eq(locked_by))
+        .exec(db)
+        .await?;
+
+    if res.rows_affected == 0 {
+        return Err(AppError::LockLost);
+    }
+
+    Ok(())
+}
+
+// Задачу мог перехватить другой воркер, поэтому все изменения идут только под своим locked_by
+async fn update_locked_task<T: ConnectionTrait>(
+    db: &T,
+    locked_by: Option<Uuid>,
+    model: task::ActiveModel,
+) -> Result<(), AppError> {
+    let locked_by = locked_by.ok_or(AppError::LockLost)?;
+
+    task::Entity::update(model)
+        .filter(task::Column::LockedBy.eq(locked_by))
+        .exec(db)
+        .await
+        .map_err(|err| match err {
+            DbErr::RecordNotUpdated => AppError::LockLost,
+            err => err.into(),
+        })?;
+
+    Ok(())
+}
+
 pub async fn unlock_task<T: ConnectionTrait>(
     db: &T,
     model: TaskModel,
-    last_topic_user_id: Uuid,
+    cursor: Uuid,
 ) -> Result<(), AppError> {
-    let payload = match model.payload.clone() {
-        task::Payload::CreateMessageTopic(mut payload) => {
-            payload.last_topic_user_id = Some(last_topic_user_id);
-            task::Payload::CreateMessageTopic(payload)
-        }
-    };
+    let mut payload = model.payload.clone();
+
+    match &mut payload {
+        task::Payload::CreateMessageTopic(payload) => payload.last_topic_user_id = Some(cursor),
+        task::Payload::DeleteMessageTopic(payload) => payload.last_entry_id = Some(cursor),
+        task::Payload::PurgeTopicUser(payload) => payload.last_entry_id = Some(cursor),
+        // Бэкфилл выполняется одним шагом и не продолжается с курсора
+        task::Payload::BackfillTopicUser(_) => {}
+    }
 
+    let locked_by = model.locked_by;
     let mut model = model.into_active_model();
 
     model.payload = Set(payload);
+    model.attempts = Set(0);
+    model.locked_at = Set(None);
+    model.locked_by = Set(None);
     model.updated_at = Set(Utc::now().naive_utc());
 
-    model.update(db).await?;
+    update_locked_task(db, locked_by, model).await?;
+
+    notify_tasks(db).await?;
+
+    Ok(())
+}
+
+// Попытки, статус и время следующего запуска считаются в одном UPDATE от значения в базе.
+// В SET tasks.attempts ещё старое значение, поэтому задержка — base * 2^(попытка - 1)
+pub async fn fail_task<T: ConnectionTrait>(
+    db: &T,
+    model: &TaskModel,
+    last_error: String,
+    max_attempts: i32,
+    backoff_base_secs: u64,
+    backoff_max_secs: u64,
+) -> Result<(), AppError> {
+    let locked_by = model.locked_by.ok_or(AppError::LockLost)?;
+    let backoff_base_secs = i64::try_from(backoff_base_secs).map_err(|_| AppError::Unreachable)?;
+    let backoff_max_secs = i64::try_from(backoff_max_secs).map_err(|_| AppError::Unreachable)?;
+    let now = Utc::now().naive_utc();
+
+    let res = task::Entity::update_many()
+        .col_expr(
+            task::Column::Attempts,
+            Expr::col(task::Column::Attempts).add(1),
+        )
+        .col_expr(
+            task::Column::Status,
+            Expr::cust_with_values(
+                "case when tasks.attempts + 1 >= $1 then 'dead' else 'active' end",
+                [max_attempts],
+            ),
+        )
+        .col_expr(
+            task::Column::NextRunAt,
+            Expr::cust_with_values(
+                "$1 + least($2 * power(2, tasks.attempts), $3) * interval '1 second'",
+                [
+                    Value::from(now),
+                    backoff_base_secs.into(),
+                    backoff_max_secs.into(),
+                ],
+            ),
+        )
+        .col_expr(task::Column::LastError, Expr::value(last_error))
+        .col_expr(
+            task::Column::LockedAt,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .col_expr(task::Column::LockedBy, Expr::value(Option::<Uuid>::None))
+        .col_expr(task::Column::UpdatedAt, Expr::value(now))
+        .filter(task::Column::TaskId.eq(model.task_id))
+        .filter(task::Column::LockedBy.eq(locked_by))
+        .exec(db)
+        .await?;
+
+    if res.rows_affected == 0 {
+        return Err(AppError::LockLost);
+    }
 
     Ok(())
 }
 
+pub async fn get_dead_tasks<T: ConnectionTrait>(
+    db: &T,
+    cursor_task_id: Option<Uuid>,
+    limit: u64,
+) -> Result<Vec<TaskModel>, AppError> {
+    let tasks = task::Entity::find()
+        .filter(task::Column::Status.eq(task::Status::Dead))
+        .apply_if(cursor_task_id, |query, v| {
+            query.filter(task::Column::TaskId.gte(v))
+        })
+        .order_by_asc(task::Column::TaskId)
+        .limit(limit)
+        .all(db)
+        .await?;
+
+    Ok(tasks)
+}
+
+pub async fn requeue_task<T: ConnectionTrait>(db: &T, task_id: Uuid) -> Result<bool, AppError> {
+    let res = task::Entity::update_many()
+        .col_expr(task::Column::Status, Expr::value(task::Status::Active))
+        .col_expr(task::Column::Attempts, Expr::value(0))
+        .col_expr(
+            task::Column::NextRunAt,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .col_expr(
+            task::Column::LockedAt,
+            Expr::value(Option::<NaiveDateTime>::None),
+        )
+        .col_expr(task::Column::LockedBy, Expr::value(Option::<Uuid>::None))
+        .col_expr(task::Column::UpdatedAt, Expr::value(Utc::now().naive_utc()))
+        .filter(task::Column::TaskId.eq(task_id))
+        .filter(task::Column::Status.eq(task::Status::Dead))
+        .exec(db)
+        .await?;
+
+    if res.rows_affected == 0 {
+        return Ok(false);
+    }
+
+    notify_tasks(db).await?;
+
+    Ok(true)
+}
+
 pub async fn delete_task<T: ConnectionTrait>(db: &T, model: TaskModel) -> Result<(), AppError> {
-    model.delete(db).await?;
+    let locked_by = model.locked_by.ok_or(AppError::LockLost)?;
+
+    let res = task::Entity::delete(model.into_active_model())
+        .filter(task::Column::LockedBy.eq(locked_by))
+        .exec(db)
+        .await?;
+
+    if res.rows_affected == 0 {
+        return Err(AppError::LockLost);
+    }
 
     Ok(())
 }
@@ -125,6 +556,7 @@ pub async fn get_topics_users_by_topic_user_id<T: ConnectionTrait>(
 ) -> Result<Vec<TopicUserModel>, AppError> {
     let topics_users = topic_user::Entity::find()
         .filter(topic_user::Column::TopicId.eq(topic_id))
+        .filter(topic_user::Column::DeletedAt.is_null())
         .apply_if(topic_user_id, |query, it| {
             query.filter(topic_user::Column::TopicUserId.lt(it))
         })
@@ -136,27 +568,246 @@ pub async fn get_topics_users_by_topic_user_id<T: ConnectionTrait>(
     Ok(topics_users)
 }
 
-pub async fn create_entry<T: ConnectionTrait>(db: &T, model: EntryModel) -> Result<(), AppError> {
-    let topic_user_ids = model.topic_user_ids.clone();
+pub async fn has_more_topics_users_than<T: ConnectionTrait>(
+    db: &T,
+    topic_id: Uuid,
+    count: u64,
+) -> Result<bool, AppError> {
+    let topic_user = topic_user::Entity::find()
+        .filter(topic_user::Column::TopicId.eq(topic_id))
+        .filter(topic_user::Column::DeletedAt.is_null())
+        .offset(count)
+        .one(db)
+        .await?;
 
-    entry::Entity::insert(model.into_active_model())
+    Ok(topic_user.is_some())
+}
+
+// Подписки пользователя на топики, которые подмешиваются при чтении; их немного, но список ограничен
+pub async fn get_fanout_topics_users_by_user_id<T: ConnectionTrait>(
+    db: &T,
+    user_id: Uuid,
+    topic_ids: Option<Vec<Uuid>>,
+    limit: u64,
+) -> Result<Vec<TopicUserModel>, AppError> {
+    let topics_users = topic_user::Entity::find()
+        .filter(topic_user::Column::UserId.eq(user_id))
+        .filter(topic_user::Column::DeletedAt.is_null())
+        .filter(
+            topic_user::Column::TopicId.in_subquery(
+                Query::select()
+                    .column(topic::Column::TopicId)
+                    .from(topic::Entity)
+                    .and_where(topic::Column::FanoutOnRead.eq(true))
+                    .to_owned(),
+            ),
+        )
+        .apply_if(topic_ids, |query, v| {
+            query.filter(topic_user::Column::TopicId.is_in(v))
+        })
+        .order_by_desc(topic_user::Column::TopicUserId)
+        .limit(limit)
+        .all(db)
+        .await?;
+
+    Ok(topics_users)
+}
+
+// Вместе с tombstone: записи отписавшихся при политике keep остаются в ленте со своими топиками
+pub async fn get_topics_users_by_topic_user_ids<T: ConnectionTrait>(
+    db: &T,
+    topic_user_ids: Vec<Uuid>,
+) -> Result<Vec<TopicUserModel>, AppError> {
+    let topics_users = topic_user::Entity::find()
+        .filter(topic_user::Column::TopicUserId.is_in(topic_user_ids))
+        .all(db)
+        .await?;
+
+    Ok(topics_users)
+}
+
+// Возвращает только вставленные записи: при конфликте у строки остаётся прежний entry_id
+pub async fn create_entries<T: ConnectionTrait>(
+    db: &T,
+    models: Vec<EntryModel>,
+) -> Result<Vec<EntryModel>, AppError> {
+    // В одном INSERT ... ON CONFLICT нельзя дважды обновить одну строку
+    let mut entries: Vec<EntryModel> = Vec::with_capacity(models.len());
+
+    for model in models {
+        match entries
+            .iter_mut()
+            .find(|it| it.message_id == model.message_id && it.user_id == model.user_id)
+        {
+            Some(entry) => entry.topic_user_ids.extend(model.topic_user_ids),
+            None => entries.push(model),
+        }
+    }
+
+    if entries.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let entry_ids: HashSet<Uuid> = entries.iter().map(|it| it.entry_id).collect();
+
+    let entries = entry::Entity::insert_many(entries.into_iter().map(|it| it.into_active_model()))
         .on_conflict(
             OnConflict::columns([entry::Column::MessageId, entry::Column::UserId])
                 .value(
                     entry::Column::TopicUserIds,
-                    Expr::cust_with_values(
+                    Expr::cust(
                         "
                         array(
                             select distinct x
-                            from unnest(entries.topic_user_ids ||  $1) x
+                            from unnest(entries.topic_user_ids || excluded.topic_user_ids) x
                         )
                         ",
-                        [topic_user_ids],
                     ),
                 )
                 .to_owned(),
         )
-        .do_nothing()
+        .exec_with_returning_many(db)
+        .await?;
+
+    Ok(entries
+        .into_iter()
+        .filter(|it| entry_ids.contains(&it.entry_id))
+        .collect())
+}
+
+pub async fn get_entries_by_message_id<T: ConnectionTrait>(
+    db: &T,
+    message_id: Uuid,
+    cursor_entry_id: Option<Uuid>,
+) -> Result<Vec<EntryModel>, AppError> {
+    let entries = entry::Entity::find()
+        .filter(entry::Column::MessageId.eq(message_id))
+        .apply_if(cursor_entry_id, |query, v| {
+            query.filter(entry::Column::EntryId.lt(v))
+        })
+        .order_by_desc(entry::Column::EntryId)
+        .limit(50)
+        .all(db)
+        .await?;
+
+    Ok(entries)
+}
+
+// Убирает все подписки владельца записи на топик, в том числе уже отменённые
+pub async fn remove_topic_from_entries<T: ConnectionTrait>(
+    db: &T,
+    entry_ids: Vec<Uuid>,
+    topic_id: Uuid,
+) -> Result<Vec<EntryModel>, AppError> {
+    entry::Entity::update_many()
+        .col_expr(
+            entry::Column::TopicUserIds,
+            Expr::cust_with_values(
+                "array(select x from unnest(entries.topic_user_ids) x where x not in (select topics_users.topic_user_id from topics_users where topics_users.user_id = entries.user_id and topics_users.topic_id = $1))",
+                [topic_id],
+            ),
+        )
+        .filter(entry::Column::EntryId.is_in(entry_ids.clone()))
+        .exec(db)
+        .await?;
+
+    delete_empty_entries(db, entry::Column::EntryId.is_in(entry_ids)).await
+}
+
+pub async fn get_entries_by_topic_user_id<T: ConnectionTrait>(
+    db: &T,
+    user_id: Uuid,
+    topic_user_id: Uuid,
+    cursor_entry_id: Option<Uuid>,
+) -> Result<Vec<EntryModel>, AppError> {
+    let entries = entry::Entity::find()
+        .filter(entry::Column::UserId.eq(user_id))
+        .filter(Expr::cust_with_values(
+            "entries.topic_user_ids @> array[$1]",
+            [topic_user_id],
+        ))
+        .apply_if(cursor_entry_id, |query, v| {
+            query.filter(entry::Column::EntryId.lt(v))
+        })
+        .order_by_desc(entry::Column::EntryId)
+        .limit(50)
+        .all(db)
+        .await?;
+
+    Ok(entries)
+}
+
+pub async fn remove_topic_user_id_from_entries<T: ConnectionTrait>(
+    db: &T,
+    entry_ids: Vec<Uuid>,
+    topic_user_id: Uuid,
+) -> Result<Vec<EntryModel>, AppError> {
+    entry::Entity::update_many()
+        .col_expr(
+            entry::Column::TopicUserIds,
+            Expr::cust_with_values("array_remove(entries.topic_user_ids, $1)", [topic_user_id]),
+        )
+        .filter(entry::Column::EntryId.is_in(entry_ids.clone()))
+        .exec(db)
+        .await?;
+
+    delete_empty_entries(db, entry::Column::EntryId.is_in(entry_ids)).await
+}
+
+// Удаляет записи, у которых не осталось подписок, и возвращает их
+async fn delete_empty_entries<T: ConnectionTrait>(
+    db: &T,
+    filter: SimpleExpr,
+) -> Result<Vec<EntryModel>, AppError> {
+    let mut query = entry::Entity::delete_many()
+        .filter(filter)
+        .filter(Expr::cust("cardinality(entries.topic_user_ids) = 0"))
+        .into_query();
+    query.returning_all();
+
+    let entries = entry::Entity::find()
+        .from_raw_sql(db.get_database_backend().build(&query))
+        .all(db)
+        .await?;
+
+    Ok(entries)
+}
+
+pub async fn create_outbox_events<T: ConnectionTrait>(
+    db: &T,
+    models: Vec<OutboxModel>,
+) -> Result<(), AppError> {
+    if models.is_empty() {
+        return Ok(());
+    }
+
+    outbox::Entity::insert_many(models.into_iter().map(|it| it.into_active_model()))
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_earliest_outbox_events<T: ConnectionTrait>(
+    db: &T,
+    limit: u64,
+) -> Result<Vec<OutboxModel>, AppError> {
+    let events = outbox::Entity::find()
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .order_by_asc(outbox::Column::EventId)
+        .limit(limit)
+        .all(db)
+        .await?;
+
+    Ok(events)
+}
+
+pub async fn delete_outbox_events<T: ConnectionTrait>(
+    db: &T,
+    event_ids: Vec<Uuid>,
+) -> Result<(), AppError> {
+    outbox::Entity::delete_many()
+        .filter(outbox::Column::EventId.is_in(event_ids))
         .exec(db)
         .await?;
 
@@ -166,11 +817,15 @@ pub async fn create_entry<T: ConnectionTrait>(db: &T, model: EntryModel) -> Resu
 pub async fn get_entries_by_user_id<T: ConnectionTrait>(
     db: &T,
     user_id: Uuid,
+    topic_ids: Option<Vec<Uuid>>,
     cursor_entry_id: Option<Uuid>,
     limit: u64,
 ) -> Result<Vec<EntryModel>, AppError> {
     let entries = entry::Entity::find()
         .filter(entry::Column::UserId.eq(user_id))
+        .apply_if(topic_ids, |query, v| {
+            query.filter(entries_from_topics(user_id, v))
+        })
         .apply_if(cursor_entry_id, |query, v| {
             query.filter(entry::Column::EntryId.lte(v))
         })
@@ -181,3 +836,104 @@ pub async fn get_entries_by_user_id<T: ConnectionTrait>(
 
     Ok(entries)
 }
+
+pub async fn get_entries_after_entry_id<T: ConnectionTrait>(
+    db: &T,
+    user_id: Uuid,
+    topic_ids: Option<Vec<Uuid>>,
+    cursor_entry_id: Option<Uuid>,
+    limit: u64,
+) -> Result<Vec<EntryModel>, AppError> {
+    let entries = entry::Entity::find()
+        .filter(entry::Column::UserId.eq(user_id))
+        .apply_if(topic_ids, |query, v| {
+            query.filter(entries_from_topics(user_id, v))
+        })
+        .apply_if(cursor_entry_id, |query, v| {
+            query.filter(entry::Column::EntryId.gt(v))
+        })
+        .order_by_asc(entry::Column::EntryId)
+        .limit(limit)
+        .all(db)
+        .await?;
+
+    Ok(entries)
+}
+
+pub async fn has_entry<T: ConnectionTrait>(
+    db: &T,
+    user_id: Uuid,
+    entry_id: Uuid,
+) -> Result<bool, AppError> {
+    let entry = entry::Entity::find()
+        .filter(entry::Column::UserId.eq(user_id))
+        .filter(entry::Column::EntryId.eq(entry_id))
+        .one(db)
+        .await?;
+
+    Ok(entry.is_some())
+}
+
+pub async fn has_message_topic_fanout_on_read<T: ConnectionTrait>(
+    db: &T,
+    topics_users: &[TopicUserModel],
+    entry_id: Uuid,
+) -> Result<bool, AppError> {
+    let message_topic = message_topic::Entity::find()
+        .filter(published_since_subscribed(topics_users))
+        .filter(message_topic::Column::FanoutOnRead.eq(true))
+        .filter(message_topic::Column::EntryId.eq(entry_id))
+        .one(db)
+        .await?;
+
+    Ok(message_topic.is_some())
+}
+
+// Маркер только двигается вперёд, чтобы устройство с устаревшим состоянием его не откатило
+pub async fn upsert_read_marker<T: ConnectionTrait>(
+    db: &T,
+    model: ReadMarkerModel,
+) -> Result<(), AppError> {
+    read_marker::Entity::insert(model.into_active_model())
+        .on_conflict(
+            OnConflict::column(read_marker::Column::UserId)
+                .update_columns([read_marker::Column::EntryId, read_marker::Column::UpdatedAt])
+                .action_and_where(Expr::cust("read_markers.entry_id < excluded.entry_id"))
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_read_marker<T: ConnectionTrait>(
+    db: &T,
+    user_id: Uuid,
+) -> Result<Option<ReadMarkerModel>, AppError> {
+    let read_marker = read_marker::Entity::find_by_id(user_id).one(db).await?;
+
+    Ok(read_marker)
+}
+
+// Сообщения топиков из подписок, опубликованные не раньше самой подписки
+fn published_since_subscribed(topics_users: &[TopicUserModel]) -> Condition {
+    topics_users
+        .iter()
+        .fold(Condition::any(), |condition, topic_user| {
+            condition.add(
+                Condition::all()
+                    .add(message_topic::Column::TopicId.eq(topic_user.topic_id))
+                    .add(message_topic::Column::PublishedAt.gte(topic_user.event_at)),
+            )
+        })
+}
+
+// Записи хотя бы из одной подписки пользователя на эти топики, в том числе уже отменённой.
+// Без user_id подзапрос собирал бы подписки всех подписчиков популярного топика
+fn entries_from_topics(user_id: Uuid, topic_ids: Vec<Uuid>) -> SimpleExpr {
+    Expr::cust_with_values(
+        "entries.topic_user_ids && array(select topics_users.topic_user_id from topics_users where topics_users.user_id = $1 and topics_users.topic_id = any($2))",
+        [Value::from(user_id), Value::from(topic_ids)],
+    )
+}
@@ -1,5 +1,8 @@
 use chrono::Utc;
 use sea_orm::entity::prelude::*;
+use uuid::{NoContext, Timestamp};
+
+use crate::app::feeds::repo::{MessageTopicModel, TopicUserModel};
 
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "entries")]
@@ -28,12 +31,62 @@ impl Model {
         }
     }
 
+    // Запись для уже опубликованного сообщения встаёт в ленту по времени публикации, а не наверх
+    pub fn published(
+        user_id: Uuid,
+        message_id: Uuid,
+        topic_user_ids: Vec<Uuid>,
+        published_at: DateTime,
+    ) -> Self {
+        Self {
+            entry_id: entry_id_at(published_at),
+            ..Self::new(user_id, message_id, topic_user_ids)
+        }
+    }
+
+    // Запись ленты для сообщения, которое подмешивается при чтении, а не хранится в entries
+    pub fn from_message_topic(
+        message_topic: MessageTopicModel,
+        topic_user: &TopicUserModel,
+    ) -> Self {
+        Self {
+            entry_id: message_topic.entry_id,
+            user_id: topic_user.user_id,
+            message_id: message_topic.message_id,
+            topic_user_ids: vec![topic_user.topic_user_id],
+            created_at: message_topic.published_at,
+            updated_at: message_topic.updated_at,
+        }
+    }
+
     #[cfg(test)]
     pub fn stub() -> Self {
         Model::new(Uuid::now_v7(), Uuid::now_v7(), vec![])
     }
 }
 
+pub fn entry_id_at(at: DateTime) -> Uuid {
+    let at = at.and_utc();
+    let secs = u64::try_from(at.timestamp()).unwrap_or_default();
+
+    Uuid::new_v7(Timestamp::from_unix(
+        NoContext,
+        secs,
+        at.timestamp_subsec_nanos(),
+    ))
+}
+
+pub fn entry_time(entry_id: Uuid) -> Option<DateTime> {
+    let (secs, nanos) = entry_id.get_timestamp()?.to_unix();
+
+    chrono::DateTime::from_timestamp(i64::try_from(secs).ok()?, nanos).map(|it| it.naive_utc())
+}
+
+// Позиция на окно раньше записи; id без времени (nil) остаётся как есть
+pub fn entry_id_before(entry_id: Uuid, window: chrono::TimeDelta) -> Uuid {
+    entry_time(entry_id).map_or(entry_id, |at| entry_id_at(at - window))
+}
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
@@ -0,0 +1,28 @@
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "inbox")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub event_id: String,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl Model {
+    pub fn new(event_id: String) -> Self {
+        let now = Utc::now().naive_utc();
+
+        Self {
+            event_id,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
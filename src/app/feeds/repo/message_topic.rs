@@ -0,0 +1,46 @@
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+
+use crate::app::feeds::repo::entry;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "messages_topics")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub message_topic_id: Uuid,
+    pub message_id: Uuid,
+    pub topic_id: Uuid,
+    // id записи ленты, если сообщение подмешивается при чтении; из времени публикации
+    pub entry_id: Uuid,
+    pub published_at: DateTime,
+    pub fanout_on_read: bool,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl Model {
+    pub fn new(
+        message_topic_id: Uuid,
+        message_id: Uuid,
+        topic_id: Uuid,
+        published_at: DateTime,
+    ) -> Self {
+        let now = Utc::now().naive_utc();
+
+        Self {
+            message_topic_id,
+            message_id,
+            topic_id,
+            entry_id: entry::entry_id_at(published_at),
+            published_at,
+            fanout_on_read: false,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,82 @@
+use std::time::SystemTime;
+
+use bzd_flux_api::events::{EntryCreated, EntryRemoved, TopicMessageCreated};
+use chrono::Utc;
+use prost::Message as _;
+use sea_orm::entity::prelude::*;
+
+use crate::app::feeds::repo::{EntryModel, MessageTopicModel};
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "outbox")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub event_id: Uuid,
+    pub tp: Type,
+    pub payload: Vec<u8>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl Model {
+    pub fn new(tp: Type, payload: Vec<u8>) -> Self {
+        let now = Utc::now().naive_utc();
+        let event_id = Uuid::now_v7();
+
+        Self {
+            event_id,
+            tp,
+            payload,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn entry_created(entry: &EntryModel) -> Self {
+        let event = EntryCreated {
+            entry_id: Some(entry.entry_id.into()),
+            user_id: Some(entry.user_id.into()),
+            message_id: Some(entry.message_id.into()),
+        };
+
+        Self::new(Type::EntryCreated, event.encode_to_vec())
+    }
+
+    pub fn entry_removed(entry: &EntryModel) -> Self {
+        let event = EntryRemoved {
+            entry_id: Some(entry.entry_id.into()),
+            user_id: Some(entry.user_id.into()),
+            message_id: Some(entry.message_id.into()),
+        };
+
+        Self::new(Type::EntryRemoved, event.encode_to_vec())
+    }
+
+    pub fn topic_message_created(message_topic: &MessageTopicModel) -> Self {
+        let event = TopicMessageCreated {
+            message_topic_id: Some(message_topic.message_topic_id.into()),
+            message_id: Some(message_topic.message_id.into()),
+            topic_id: Some(message_topic.topic_id.into()),
+            entry_id: Some(message_topic.entry_id.into()),
+            published_at: Some(SystemTime::from(message_topic.published_at.and_utc()).into()),
+        };
+
+        Self::new(Type::TopicMessageCreated, event.encode_to_vec())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+pub enum Type {
+    #[sea_orm(string_value = "entry_created")]
+    EntryCreated,
+    #[sea_orm(string_value = "entry_removed")]
+    EntryRemoved,
+    #[sea_orm(string_value = "topic_message_created")]
+    TopicMessageCreated,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,30 @@
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "read_markers")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: Uuid,
+    pub entry_id: Uuid,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl Model {
+    pub fn new(user_id: Uuid, entry_id: Uuid) -> Self {
+        let now = Utc::now().naive_utc();
+
+        Self {
+            user_id,
+            entry_id,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
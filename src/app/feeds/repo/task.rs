@@ -12,6 +12,11 @@ pub struct Model {
     pub created_at: DateTime,
     pub updated_at: DateTime,
     pub locked_at: Option<DateTime>,
+    pub locked_by: Option<Uuid>,
+    pub status: Status,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_run_at: Option<DateTime>,
 }
 
 impl Model {
@@ -25,13 +30,30 @@ impl Model {
             created_at: now,
             updated_at: now,
             locked_at: None,
+            locked_by: None,
+            status: Status::Active,
+            attempts: 0,
+            last_error: None,
+            next_run_at: None,
         }
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+pub enum Status {
+    #[sea_orm(string_value = "active")]
+    Active,
+    #[sea_orm(string_value = "dead")]
+    Dead,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
 pub enum Payload {
     CreateMessageTopic(CreateMessageTopic),
+    DeleteMessageTopic(DeleteMessageTopic),
+    PurgeTopicUser(PurgeTopicUser),
+    BackfillTopicUser(BackfillTopicUser),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -41,6 +63,28 @@ pub struct CreateMessageTopic {
     pub last_topic_user_id: Option<Uuid>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeleteMessageTopic {
+    pub message_id: Uuid,
+    pub topic_id: Uuid,
+    pub last_entry_id: Option<Uuid>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PurgeTopicUser {
+    pub topic_user_id: Uuid,
+    pub user_id: Uuid,
+    pub last_entry_id: Option<Uuid>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackfillTopicUser {
+    pub topic_user_id: Uuid,
+    pub topic_id: Uuid,
+    pub user_id: Uuid,
+    pub limit: u64,
+}
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
@@ -0,0 +1,32 @@
+use chrono::Utc;
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "topics")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub topic_id: Uuid,
+    pub fanout_on_read: bool,
+    pub checked_at: DateTime,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+impl Model {
+    pub fn new(topic_id: Uuid, fanout_on_read: bool) -> Self {
+        let now = Utc::now().naive_utc();
+
+        Self {
+            topic_id,
+            fanout_on_read,
+            checked_at: now,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -8,6 +8,10 @@ pub struct Model {
     pub topic_user_id: Uuid,
     pub user_id: Uuid,
     pub topic_id: Uuid,
+    pub event_at: DateTime,
+    // Порядковый номер события в стриме, разрешает равенство event_at
+    pub event_seq: i64,
+    pub deleted_at: Option<DateTime>,
     pub created_at: DateTime,
     pub updated_at: DateTime,
 }
@@ -20,6 +24,9 @@ impl Model {
             topic_user_id,
             user_id,
             topic_id,
+            event_at: now,
+            event_seq: 0,
+            deleted_at: None,
             created_at: now,
             updated_at: now,
         }
@@ -1,421 +1,3521 @@
-use sea_orm::DbConn;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+};
+
+use chrono::{TimeDelta, Utc};
+use sea_orm::{DbConn, TransactionTrait as _};
+use tokio::{
+    select,
+    sync::{broadcast::error::RecvError, mpsc},
+};
 use uuid::Uuid;
+use validator::ValidateArgs as _;
 
 use crate::app::{
     error::AppError,
     feeds::{
-        repo::{self, EntryModel, TaskModel, task::Payload},
-        settings::FeedsSettings,
+        hub::Hub,
+        page_token::{self, PageToken},
+        repo::{
+            self, EntryModel, InboxModel, OutboxModel, ReadMarkerModel, TaskModel, TopicModel,
+            task::Payload,
+        },
+        settings::{FeedsSettings, UnsubscribePolicy},
     },
 };
 
 pub async fn create_entries_from_message_topic(
     db: &DbConn,
+    settings: &FeedsSettings,
     req: create_entries_from_message_topic::Request,
 ) -> Result<Option<Uuid>, AppError> {
+    // Для крупных топиков сообщение не раскладывается по лентам, а подмешивается при чтении
+    if req.last_topic_user_id.is_none()
+        && is_fanout_on_read_topic(db, settings, req.topic_id).await?
+    {
+        let tx = db.begin().await?;
+
+        // Открытые потоки подписчиков получат сообщение из outbox, как и обычные записи
+        let messages_topics =
+            repo::mark_message_topic_as_fanout_on_read(&tx, req.message_id, req.topic_id).await?;
+        let events = messages_topics
+            .iter()
+            .map(OutboxModel::topic_message_created)
+            .collect();
+        repo::create_outbox_events(&tx, events).await?;
+
+        tx.commit().await?;
+
+        return Ok(None);
+    }
+
     let topics_users =
         repo::get_topics_users_by_topic_user_id(db, req.topic_id, req.last_topic_user_id).await?;
 
-    // TODO: нужно сделать параллельно
-    for topic_user in topics_users.clone() {
-        let entry = EntryModel::new(
-            topic_user.user_id,
-            req.message_id,
-            vec![topic_user.topic_user_id],
-        );
-        repo::create_entry(db, entry).await?;
+    let entries = topics_users
+        .iter()
+        .map(|it| EntryModel::new(it.user_id, req.message_id, vec![it.topic_user_id]))
+        .collect();
+
+    let tx = db.begin().await?;
+
+    // Сообщение уже убрали из топика: дальше рассылать нельзя, иначе удаление не догонит записи
+    if !repo::has_message_topic(&tx, req.message_id, req.topic_id).await? {
+        return Ok(None);
+    }
+
+    let entries = repo::create_entries(&tx, entries).await?;
+    let events = entries.iter().map(OutboxModel::entry_created).collect();
+    repo::create_outbox_events(&tx, events).await?;
+
+    tx.commit().await?;
+
+    Ok(topics_users.last().map(|it| it.topic_user_id))
+}
+
+// Режим хранится у топика: подписчиков пересчитываем не чаще fanout.check_interval_secs,
+// а не сканом на каждое сообщение
+async fn is_fanout_on_read_topic(
+    db: &DbConn,
+    settings: &FeedsSettings,
+    topic_id: Uuid,
+) -> Result<bool, AppError> {
+    let check_interval = i64::try_from(settings.fanout.check_interval_secs)
+        .ok()
+        .and_then(TimeDelta::try_seconds)
+        .ok_or(AppError::Unreachable)?;
+
+    if let Some(topic) = repo::get_topic(db, topic_id).await?
+        && (topic.fanout_on_read || topic.checked_at + check_interval > Utc::now().naive_utc())
+    {
+        return Ok(topic.fanout_on_read);
+    }
+
+    let fanout_on_read =
+        repo::has_more_topics_users_than(db, topic_id, settings.fanout.on_read_threshold).await?;
+
+    repo::upsert_topic(db, TopicModel::new(topic_id, fanout_on_read)).await?;
+
+    Ok(fanout_on_read)
+}
+
+pub mod create_entries_from_message_topic {
+    use uuid::Uuid;
+
+    use crate::app::feeds::repo::task::CreateMessageTopic;
+
+    #[derive(Clone)]
+    pub struct Request {
+        pub message_id: Uuid,
+        pub topic_id: Uuid,
+        pub last_topic_user_id: Option<Uuid>,
+    }
+
+    impl From<CreateMessageTopic> for Request {
+        fn from(payload: CreateMessageTopic) -> Self {
+            Self {
+                message_id: payload.message_id,
+                topic_id: payload.topic_id,
+                last_topic_user_id: payload.last_topic_user_id,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use chrono::{TimeDelta, Utc};
+        use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult, Transaction};
+        use uuid::Uuid;
+
+        use crate::app::feeds::{
+            repo::{EntryModel, MessageTopicModel, TopicModel, TopicUserModel},
+            service::{self, create_entries_from_message_topic::Request},
+            settings::FeedsSettings,
+        };
+
+        fn message_topic(req: &Request) -> MessageTopicModel {
+            MessageTopicModel::new(
+                Uuid::now_v7(),
+                req.message_id,
+                req.topic_id,
+                Utc::now().naive_utc(),
+            )
+        }
+
+        #[tokio::test]
+        async fn test_ok_create_entries_from_message_topic() -> Result<(), Error> {
+            let req = Request {
+                message_id: Uuid::now_v7(),
+                topic_id: Uuid::now_v7(),
+                last_topic_user_id: None,
+            };
+
+            let topics_users = vec![
+                TopicUserModel::new(Uuid::now_v7(), Uuid::now_v7(), req.topic_id),
+                TopicUserModel::new(Uuid::now_v7(), Uuid::now_v7(), req.topic_id),
+            ];
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<TopicModel>::new()])
+                .append_query_results([vec![], topics_users.clone()])
+                .append_exec_results([MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 1,
+                }])
+                .append_query_results([[message_topic(&req)]])
+                .append_query_results([Vec::<EntryModel>::new()])
+                .into_connection();
+
+            let res = service::create_entries_from_message_topic(&db, &FeedsSettings::stub(), req)
+                .await?;
+
+            assert_eq!(res, topics_users.last().map(|it| it.topic_user_id));
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 5);
+            assert!(
+                log[2].statements()[0]
+                    .sql
+                    .starts_with(r#"INSERT INTO "topics""#)
+            );
+            assert!(log[4].statements()[1].sql.ends_with("FOR SHARE"));
+
+            let insert = &log[4].statements()[2];
+
+            assert!(insert.sql.starts_with(r#"INSERT INTO "entries""#));
+            assert!(insert.sql.contains("RETURNING"));
+            assert!(
+                insert
+                    .sql
+                    .contains(r#"ON CONFLICT ("message_id", "user_id")"#)
+            );
+            assert_eq!(insert.values.as_ref().map(|it| it.0.len()), Some(12));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_create_entries_from_deleted_message_topic() -> Result<(), Error> {
+            let req = Request {
+                message_id: Uuid::now_v7(),
+                topic_id: Uuid::now_v7(),
+                last_topic_user_id: Some(Uuid::now_v7()),
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[TopicUserModel::new(
+                    Uuid::now_v7(),
+                    Uuid::now_v7(),
+                    req.topic_id,
+                )]])
+                .append_query_results([Vec::<MessageTopicModel>::new()])
+                .into_connection();
+
+            let res = service::create_entries_from_message_topic(&db, &FeedsSettings::stub(), req)
+                .await?;
+
+            assert_eq!(res, None);
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 2);
+            assert!(
+                log[1]
+                    .statements()
+                    .iter()
+                    .all(|it| !it.sql.starts_with(r#"INSERT INTO "entries""#))
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_create_entries_from_message_topic_on_read() -> Result<(), Error> {
+            let req = Request {
+                message_id: Uuid::now_v7(),
+                topic_id: Uuid::now_v7(),
+                last_topic_user_id: None,
+            };
+
+            let settings = FeedsSettings::stub();
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<TopicModel>::new()])
+                .append_query_results([[TopicUserModel::new(
+                    Uuid::now_v7(),
+                    Uuid::now_v7(),
+                    req.topic_id,
+                )]])
+                .append_exec_results([
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 1,
+                    },
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 1,
+                    },
+                ])
+                .append_query_results([[MessageTopicModel {
+                    fanout_on_read: true,
+                    ..message_topic(&req)
+                }]])
+                .into_connection();
+
+            let res =
+                service::create_entries_from_message_topic(&db, &settings, req.clone()).await?;
+
+            assert_eq!(res, None);
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(
+                log[1],
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "topics_users"."topic_user_id", "topics_users"."user_id", "topics_users"."topic_id", "topics_users"."event_at", "topics_users"."event_seq", "topics_users"."deleted_at", "topics_users"."created_at", "topics_users"."updated_at" FROM "topics_users" WHERE "topics_users"."topic_id" = $1 AND "topics_users"."deleted_at" IS NULL LIMIT $2 OFFSET $3"#,
+                    [
+                        req.topic_id.into(),
+                        1u64.into(),
+                        settings.fanout.on_read_threshold.into()
+                    ]
+                )
+            );
+            assert_eq!(
+                log[2].statements()[0].sql,
+                r#"INSERT INTO "topics" ("topic_id", "fanout_on_read", "checked_at", "created_at", "updated_at") VALUES ($1, $2, $3, $4, $5) ON CONFLICT ("topic_id") DO UPDATE SET "fanout_on_read" = topics.fanout_on_read or excluded.fanout_on_read, "checked_at" = "excluded"."checked_at", "updated_at" = "excluded"."updated_at""#
+            );
+            assert!(
+                log[3].statements()[1]
+                    .sql
+                    .starts_with(r#"UPDATE "messages_topics" SET "fanout_on_read" = $1"#)
+            );
+            assert!(log[3].statements()[1].sql.contains("RETURNING"));
+            assert!(
+                log[3].statements()[2]
+                    .sql
+                    .starts_with(r#"INSERT INTO "outbox""#)
+            );
+            assert_eq!(log.len(), 4);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_create_entries_from_message_topic_on_read_topic() -> Result<(), Error> {
+            let req = Request {
+                message_id: Uuid::now_v7(),
+                topic_id: Uuid::now_v7(),
+                last_topic_user_id: None,
+            };
+
+            // Давно проверенный, но уже крупный топик не пересчитывается
+            let topic = TopicModel {
+                checked_at: (Utc::now() - TimeDelta::days(1)).naive_utc(),
+                ..TopicModel::new(req.topic_id, true)
+            };
+
+            // Строка уже отмечена прошлой попыткой задачи: событие повторно не пишется
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[topic]])
+                .append_query_results([Vec::<MessageTopicModel>::new()])
+                .into_connection();
+
+            let res = service::create_entries_from_message_topic(&db, &FeedsSettings::stub(), req)
+                .await?;
+
+            assert_eq!(res, None);
+
+            let log = db.into_transaction_log();
+
+            assert!(
+                log[0].statements()[0]
+                    .sql
+                    .starts_with(r#"SELECT "topics"."topic_id""#)
+            );
+            assert!(
+                log[1].statements()[1]
+                    .sql
+                    .starts_with(r#"UPDATE "messages_topics" SET "fanout_on_read" = $1"#)
+            );
+            assert!(
+                log[1]
+                    .statements()
+                    .iter()
+                    .all(|it| !it.sql.starts_with(r#"INSERT INTO "outbox""#))
+            );
+            assert_eq!(log.len(), 2);
+
+            Ok(())
+        }
+    }
+}
+
+// Идёт по записям сообщения, а не по текущим подпискам: отписанные тоже должны потерять запись
+pub async fn delete_entries_from_message_topic(
+    db: &DbConn,
+    req: delete_entries_from_message_topic::Request,
+) -> Result<Option<Uuid>, AppError> {
+    let entries = repo::get_entries_by_message_id(db, req.message_id, req.last_entry_id).await?;
+
+    if !entries.is_empty() {
+        let entry_ids = entries.iter().map(|it| it.entry_id).collect();
+
+        let tx = db.begin().await?;
+        let removed = repo::remove_topic_from_entries(&tx, entry_ids, req.topic_id).await?;
+        let events = removed.iter().map(OutboxModel::entry_removed).collect();
+        repo::create_outbox_events(&tx, events).await?;
+        tx.commit().await?;
+    }
+
+    Ok(entries.last().map(|it| it.entry_id))
+}
+
+pub mod delete_entries_from_message_topic {
+    use uuid::Uuid;
+
+    use crate::app::feeds::repo::task::DeleteMessageTopic;
+
+    #[derive(Clone)]
+    pub struct Request {
+        pub message_id: Uuid,
+        pub topic_id: Uuid,
+        pub last_entry_id: Option<Uuid>,
+    }
+
+    impl From<DeleteMessageTopic> for Request {
+        fn from(payload: DeleteMessageTopic) -> Self {
+            Self {
+                message_id: payload.message_id,
+                topic_id: payload.topic_id,
+                last_entry_id: payload.last_entry_id,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult, Statement, Transaction};
+        use uuid::Uuid;
+
+        use crate::app::feeds::{
+            repo::EntryModel,
+            service::{self, delete_entries_from_message_topic::Request},
+        };
+
+        #[tokio::test]
+        async fn test_ok_delete_entries_from_message_topic() -> Result<(), Error> {
+            let req = Request {
+                message_id: Uuid::now_v7(),
+                topic_id: Uuid::now_v7(),
+                last_entry_id: None,
+            };
+
+            let entries = vec![EntryModel::stub(), EntryModel::stub()];
+            let entry_ids: Vec<Uuid> = entries.iter().map(|it| it.entry_id).collect();
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([entries.clone()])
+                .append_query_results([[EntryModel::stub()]])
+                .append_exec_results([
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 2,
+                    },
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 1,
+                    },
+                ])
+                .into_connection();
+
+            let res = service::delete_entries_from_message_topic(&db, req.clone()).await?;
+
+            assert_eq!(res, entry_ids.last().copied());
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 2);
+            assert_eq!(
+                log[0],
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."message_id" = $1 ORDER BY "entries"."entry_id" DESC LIMIT $2"#,
+                    [req.message_id.into(), 50u64.into()]
+                )
+            );
+            assert_eq!(
+                log[1].statements()[..3],
+                [
+                    Statement::from_string(DatabaseBackend::Postgres, "BEGIN"),
+                    Statement::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"UPDATE "entries" SET "topic_user_ids" = array(select x from unnest(entries.topic_user_ids) x where x not in (select topics_users.topic_user_id from topics_users where topics_users.user_id = entries.user_id and topics_users.topic_id = $1)) WHERE "entries"."entry_id" IN ($2, $3)"#,
+                        [
+                            req.topic_id.into(),
+                            entry_ids[0].into(),
+                            entry_ids[1].into()
+                        ]
+                    ),
+                    Statement::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"DELETE FROM "entries" WHERE "entries"."entry_id" IN ($1, $2) AND (cardinality(entries.topic_user_ids) = 0) RETURNING *"#,
+                        [entry_ids[0].into(), entry_ids[1].into()]
+                    ),
+                ]
+            );
+            assert!(
+                log[1].statements()[3]
+                    .sql
+                    .starts_with(r#"INSERT INTO "outbox""#)
+            );
+            assert_eq!(log[1].statements().len(), 5);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_delete_entries_from_message_topic_without_entries() -> Result<(), Error> {
+            let req = Request {
+                message_id: Uuid::now_v7(),
+                topic_id: Uuid::now_v7(),
+                last_entry_id: Some(Uuid::now_v7()),
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<EntryModel>::new()])
+                .into_connection();
+
+            let res = service::delete_entries_from_message_topic(&db, req.clone()).await?;
+
+            assert_eq!(res, None);
+            assert_eq!(db.into_transaction_log().len(), 1);
+
+            Ok(())
+        }
+    }
+}
+
+pub async fn handle_messages_topics(
+    db: &DbConn,
+    reqs: Vec<handle_messages_topics::Request>,
+) -> Result<(), AppError> {
+    let tx = db.begin().await?;
+
+    // Повтор внутри пачки — такой же дубль, как и повторная доставка
+    let mut event_ids = HashSet::new();
+    let reqs: Vec<_> = reqs
+        .into_iter()
+        .filter(|it| event_ids.insert(it.event_id.clone()))
+        .collect();
+
+    let inbox = reqs
+        .iter()
+        .map(|it| InboxModel::new(it.event_id.clone()))
+        .collect();
+    let event_ids = repo::create_inbox_events(&tx, inbox).await?;
+    let reqs: Vec<_> = reqs
+        .into_iter()
+        .filter(|it| event_ids.contains(&it.event_id))
+        .collect();
+
+    // Строка связи определяется последним событием пачки, задачи создаются на каждое
+    let mut last = BTreeMap::new();
+
+    for req in &reqs {
+        last.insert(req.message_topic_id, req);
+    }
+
+    let (created, deleted): (Vec<_>, Vec<_>) = last
+        .into_values()
+        .partition(|it| it.tp == handle_messages_topics::Type::Created);
+
+    repo::delete_messages_topics(&tx, deleted.iter().map(|it| it.message_topic_id).collect())
+        .await?;
+    repo::upsert_messages_topics(
+        &tx,
+        created.into_iter().map(|it| it.clone().into()).collect(),
+    )
+    .await?;
+
+    let tasks = reqs
+        .into_iter()
+        .map(|req| match req.tp {
+            handle_messages_topics::Type::Created => {
+                TaskModel::new(Payload::CreateMessageTopic(req.into()))
+            }
+            handle_messages_topics::Type::Deleted => {
+                TaskModel::new(Payload::DeleteMessageTopic(req.into()))
+            }
+        })
+        .collect();
+
+    repo::create_tasks(&tx, tasks).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub mod handle_messages_topics {
+    use chrono::NaiveDateTime;
+    use uuid::Uuid;
+
+    use crate::app::feeds::repo::{
+        MessageTopicModel,
+        task::{CreateMessageTopic, DeleteMessageTopic},
+    };
+
+    #[derive(Clone)]
+    pub struct Request {
+        pub event_id: String,
+        pub tp: Type,
+        pub message_topic_id: Uuid,
+        pub topic_id: Uuid,
+        pub message_id: Uuid,
+        pub published_at: NaiveDateTime,
+    }
+
+    pub type Type = bzd_messages_api::events::message_topic::Type;
+
+    impl From<Request> for CreateMessageTopic {
+        fn from(req: Request) -> Self {
+            Self {
+                message_id: req.message_id,
+                topic_id: req.topic_id,
+                last_topic_user_id: None,
+            }
+        }
     }
 
-    Ok(topics_users.last().map(|it| it.topic_user_id))
-}
+    impl From<Request> for MessageTopicModel {
+        fn from(req: Request) -> Self {
+            Self::new(
+                req.message_topic_id,
+                req.message_id,
+                req.topic_id,
+                req.published_at,
+            )
+        }
+    }
+
+    impl From<Request> for DeleteMessageTopic {
+        fn from(req: Request) -> Self {
+            Self {
+                message_id: req.message_id,
+                topic_id: req.topic_id,
+                last_entry_id: None,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use chrono::Utc;
+        use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+        use uuid::Uuid;
+
+        use crate::app::feeds::{
+            repo::InboxModel,
+            service::{
+                self,
+                handle_messages_topics::{Request, Type},
+            },
+        };
+
+        fn request(tp: Type, message_topic_id: Uuid) -> Request {
+            Request {
+                event_id: Uuid::now_v7().to_string(),
+                tp,
+                message_topic_id,
+                topic_id: Uuid::now_v7(),
+                message_id: Uuid::now_v7(),
+                published_at: Utc::now().naive_utc(),
+            }
+        }
+
+        #[tokio::test]
+        async fn test_ok_handle_messages_topics_duplicate() -> Result<(), Error> {
+            let req = request(Type::Created, Uuid::now_v7());
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<InboxModel>::new()])
+                .into_connection();
+
+            service::handle_messages_topics(&db, vec![req]).await?;
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 1);
+            assert_eq!(log[0].statements().len(), 3);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_handle_messages_topics_batch() -> Result<(), Error> {
+            let message_topic_id = Uuid::now_v7();
+            let created = request(Type::Created, message_topic_id);
+            let deleted = request(Type::Deleted, message_topic_id);
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[
+                    InboxModel::new(created.event_id.clone()),
+                    InboxModel::new(deleted.event_id.clone()),
+                ]])
+                .append_exec_results([
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 0,
+                    },
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 2,
+                    },
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 0,
+                    },
+                ])
+                .into_connection();
+
+            service::handle_messages_topics(&db, vec![created.clone(), created, deleted]).await?;
+
+            let log = db.into_transaction_log();
+            let statements = log[0].statements();
+
+            assert_eq!(log.len(), 1);
+            assert!(statements.get(1).is_some_and(|it| {
+                it.sql.starts_with(r#"INSERT INTO "inbox""#)
+                    && it.sql.contains("($4, $5, $6) ON CONFLICT")
+                    && !it.sql.contains("$7")
+            }));
+            // Связь удалена последним событием пачки, вставлять её уже незачем
+            assert!(
+                statements
+                    .get(2)
+                    .is_some_and(|it| it.sql.starts_with(r#"DELETE FROM "messages_topics""#))
+            );
+            assert!(
+                statements
+                    .get(3)
+                    .is_some_and(|it| it.sql.starts_with(r#"INSERT INTO "tasks""#))
+            );
+            assert_eq!(
+                statements.get(4).map(|it| it.sql.as_str()),
+                Some("NOTIFY bzd_flux_tasks")
+            );
+            assert_eq!(statements.len(), 6);
+
+            Ok(())
+        }
+    }
+}
+
+pub async fn purge_entries_from_topic_user(
+    db: &DbConn,
+    req: purge_entries_from_topic_user::Request,
+) -> Result<Option<Uuid>, AppError> {
+    let entries =
+        repo::get_entries_by_topic_user_id(db, req.user_id, req.topic_user_id, req.last_entry_id)
+            .await?;
+
+    if !entries.is_empty() {
+        let entry_ids = entries.iter().map(|it| it.entry_id).collect();
+
+        let tx = db.begin().await?;
+        let removed =
+            repo::remove_topic_user_id_from_entries(&tx, entry_ids, req.topic_user_id).await?;
+        let events = removed.iter().map(OutboxModel::entry_removed).collect();
+        repo::create_outbox_events(&tx, events).await?;
+        tx.commit().await?;
+    }
+
+    Ok(entries.last().map(|it| it.entry_id))
+}
+
+pub mod purge_entries_from_topic_user {
+    use uuid::Uuid;
+
+    use crate::app::feeds::repo::task::PurgeTopicUser;
+
+    pub struct Request {
+        pub topic_user_id: Uuid,
+        pub user_id: Uuid,
+        pub last_entry_id: Option<Uuid>,
+    }
+
+    impl From<PurgeTopicUser> for Request {
+        fn from(payload: PurgeTopicUser) -> Self {
+            Self {
+                topic_user_id: payload.topic_user_id,
+                user_id: payload.user_id,
+                last_entry_id: payload.last_entry_id,
+            }
+        }
+    }
+}
+
+pub async fn create_entries_from_topic_user(
+    db: &DbConn,
+    req: create_entries_from_topic_user::Request,
+) -> Result<(), AppError> {
+    let messages_topics =
+        repo::get_latest_messages_topics_by_topic_id(db, req.topic_id, req.limit).await?;
+
+    let entries = messages_topics
+        .iter()
+        .rev()
+        .map(|it| {
+            EntryModel::published(
+                req.user_id,
+                it.message_id,
+                vec![req.topic_user_id],
+                it.published_at,
+            )
+        })
+        .collect();
+
+    let tx = db.begin().await?;
+
+    let entries = repo::create_entries(&tx, entries).await?;
+    let events = entries.iter().map(OutboxModel::entry_created).collect();
+    repo::create_outbox_events(&tx, events).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub mod create_entries_from_topic_user {
+    use uuid::Uuid;
+
+    use crate::app::feeds::repo::task::BackfillTopicUser;
+
+    #[derive(Clone)]
+    pub struct Request {
+        pub topic_user_id: Uuid,
+        pub topic_id: Uuid,
+        pub user_id: Uuid,
+        pub limit: u64,
+    }
+
+    impl From<BackfillTopicUser> for Request {
+        fn from(payload: BackfillTopicUser) -> Self {
+            Self {
+                topic_user_id: payload.topic_user_id,
+                topic_id: payload.topic_id,
+                user_id: payload.user_id,
+                limit: payload.limit,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use chrono::{TimeDelta, Utc};
+        use sea_orm::{DatabaseBackend, MockDatabase, Transaction, Value};
+        use uuid::Uuid;
+
+        use crate::app::{
+            error::AppError,
+            feeds::{
+                repo::{EntryModel, MessageTopicModel},
+                service::{self, create_entries_from_topic_user::Request},
+            },
+        };
+
+        #[tokio::test]
+        async fn test_ok_create_entries_from_topic_user() -> Result<(), Error> {
+            let req = Request {
+                topic_user_id: Uuid::now_v7(),
+                topic_id: Uuid::now_v7(),
+                user_id: Uuid::now_v7(),
+                limit: 2,
+            };
+
+            let messages_topics = vec![
+                MessageTopicModel::new(
+                    Uuid::now_v7(),
+                    Uuid::now_v7(),
+                    req.topic_id,
+                    Utc::now().naive_utc(),
+                ),
+                MessageTopicModel::new(
+                    Uuid::now_v7(),
+                    Uuid::now_v7(),
+                    req.topic_id,
+                    (Utc::now() - TimeDelta::days(1)).naive_utc(),
+                ),
+            ];
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([messages_topics.clone()])
+                .append_query_results([Vec::<EntryModel>::new()])
+                .into_connection();
+
+            service::create_entries_from_topic_user(&db, req.clone()).await?;
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 2);
+
+            // Первой вставляется самая старая запись, и её id несёт время публикации
+            let entry_id = match log[1].statements()[1].values.as_ref().map(|it| &it.0[0]) {
+                Some(Value::Uuid(Some(entry_id))) => **entry_id,
+                _ => return Err(AppError::Unreachable.into()),
+            };
+            assert_eq!(
+                entry_id.get_timestamp().map(|it| it.to_unix().0),
+                u64::try_from(messages_topics[1].published_at.and_utc().timestamp()).ok()
+            );
+            assert_eq!(
+                log[0],
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "messages_topics"."message_topic_id", "messages_topics"."message_id", "messages_topics"."topic_id", "messages_topics"."entry_id", "messages_topics"."published_at", "messages_topics"."fanout_on_read", "messages_topics"."created_at", "messages_topics"."updated_at" FROM "messages_topics" WHERE "messages_topics"."topic_id" = $1 ORDER BY "messages_topics"."published_at" DESC LIMIT $2"#,
+                    [req.topic_id.into(), req.limit.into()]
+                )
+            );
+
+            Ok(())
+        }
+    }
+}
+
+pub async fn handle_topics_users(
+    db: &DbConn,
+    settings: &FeedsSettings,
+    reqs: Vec<handle_topics_users::Request>,
+) -> Result<(), AppError> {
+    let tx = db.begin().await?;
+
+    let topics_users =
+        repo::upsert_topics_users(&tx, reqs.into_iter().map(Into::into).collect()).await?;
+
+    let mut tasks = vec![];
+
+    for req in topics_users
+        .into_iter()
+        .map(handle_topics_users::Request::from)
+    {
+        let payload = match req.tp {
+            handle_topics_users::Type::Created => match settings.limits.backfill {
+                0 => None,
+                limit => Some(Payload::BackfillTopicUser((req, limit).into())),
+            },
+            handle_topics_users::Type::Deleted => match settings.unsubscribe.policy {
+                UnsubscribePolicy::Keep => None,
+                UnsubscribePolicy::Retract => Some(Payload::PurgeTopicUser(req.into())),
+            },
+        };
+
+        if let Some(payload) = payload {
+            tasks.push(TaskModel::new(payload));
+        }
+    }
+
+    repo::create_tasks(&tx, tasks).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub mod handle_topics_users {
+    use chrono::NaiveDateTime;
+    use uuid::Uuid;
+
+    use crate::app::feeds::repo::{
+        self,
+        task::{BackfillTopicUser, PurgeTopicUser},
+    };
+
+    #[derive(Clone)]
+    pub struct Request {
+        pub tp: Type,
+        pub topic_user_id: Uuid,
+        pub topic_id: Uuid,
+        pub user_id: Uuid,
+        pub event_at: NaiveDateTime,
+        pub event_seq: i64,
+    }
+
+    impl From<Request> for repo::topic_user::Model {
+        fn from(req: Request) -> Self {
+            let deleted_at = match req.tp {
+                Type::Created => None,
+                Type::Deleted => Some(req.event_at),
+            };
+
+            Self {
+                event_at: req.event_at,
+                event_seq: req.event_seq,
+                deleted_at,
+                ..Self::new(req.topic_user_id, req.user_id, req.topic_id)
+            }
+        }
+    }
+
+    // Применённая строка несёт всё, что нужно для задачи: тип события восстанавливается по tombstone
+    impl From<repo::topic_user::Model> for Request {
+        fn from(model: repo::topic_user::Model) -> Self {
+            let tp = match model.deleted_at {
+                Some(_) => Type::Deleted,
+                None => Type::Created,
+            };
+
+            Self {
+                tp,
+                topic_user_id: model.topic_user_id,
+                topic_id: model.topic_id,
+                user_id: model.user_id,
+                event_at: model.event_at,
+                event_seq: model.event_seq,
+            }
+        }
+    }
+
+    impl From<(Request, u64)> for BackfillTopicUser {
+        fn from((req, limit): (Request, u64)) -> Self {
+            Self {
+                topic_user_id: req.topic_user_id,
+                topic_id: req.topic_id,
+                user_id: req.user_id,
+                limit,
+            }
+        }
+    }
+
+    impl From<Request> for PurgeTopicUser {
+        fn from(req: Request) -> Self {
+            Self {
+                topic_user_id: req.topic_user_id,
+                user_id: req.user_id,
+                last_entry_id: None,
+            }
+        }
+    }
+
+    pub type Type = bzd_messages_api::events::topic_user::Type;
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use bzd_messages_api::events::topic_user::Type;
+        use chrono::Utc;
+        use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult, Statement};
+        use uuid::Uuid;
+
+        use crate::app::feeds::{
+            repo::TopicUserModel,
+            service::{self, handle_topics_users::Request},
+            settings::{FeedsSettings, LimitsSettings, UnsubscribePolicy, UnsubscribeSettings},
+        };
+
+        const UPSERT_SQL: &str = r#"INSERT INTO "topics_users" ("topic_user_id", "user_id", "topic_id", "event_at", "event_seq", "deleted_at", "created_at", "updated_at") VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT ("topic_user_id") DO UPDATE SET "event_at" = "excluded"."event_at", "event_seq" = "excluded"."event_seq", "deleted_at" = "excluded"."deleted_at", "updated_at" = "excluded"."updated_at" WHERE (topics_users.event_at, topics_users.event_seq) < (excluded.event_at, excluded.event_seq) RETURNING"#;
+
+        fn request(tp: Type) -> Request {
+            Request {
+                tp,
+                topic_user_id: Uuid::now_v7(),
+                topic_id: Uuid::now_v7(),
+                user_id: Uuid::now_v7(),
+                event_at: Utc::now().naive_utc(),
+                event_seq: 1,
+            }
+        }
+
+        fn retract() -> FeedsSettings {
+            FeedsSettings {
+                unsubscribe: UnsubscribeSettings {
+                    policy: UnsubscribePolicy::Retract,
+                },
+                ..FeedsSettings::stub()
+            }
+        }
+
+        #[tokio::test]
+        async fn test_ok_handle_topics_users_delete() -> Result<(), Error> {
+            let req = request(Type::Deleted);
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[TopicUserModel::from(req.clone())]])
+                .into_connection();
+
+            service::handle_topics_users(&db, &FeedsSettings::stub(), vec![req]).await?;
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 1);
+            assert!(
+                log[0]
+                    .statements()
+                    .get(1)
+                    .is_some_and(|it| it.sql.starts_with(UPSERT_SQL))
+            );
+            assert_eq!(log[0].statements().len(), 3);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_handle_topics_users_delete_with_retract() -> Result<(), Error> {
+            let req = request(Type::Deleted);
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[TopicUserModel::from(req.clone())]])
+                .append_exec_results([
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 1,
+                    },
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 0,
+                    },
+                ])
+                .into_connection();
+
+            service::handle_topics_users(&db, &retract(), vec![req]).await?;
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 1);
+            assert_eq!(
+                log[0].statements().first(),
+                Some(&Statement::from_string(DatabaseBackend::Postgres, "BEGIN"))
+            );
+            assert!(
+                log[0]
+                    .statements()
+                    .get(1)
+                    .is_some_and(|it| it.sql.starts_with(UPSERT_SQL))
+            );
+            assert!(
+                log[0]
+                    .statements()
+                    .get(2)
+                    .is_some_and(|it| it.sql.starts_with(r#"INSERT INTO "tasks""#))
+            );
+            assert_eq!(
+                log[0].statements().get(3),
+                Some(&Statement::from_string(
+                    DatabaseBackend::Postgres,
+                    "NOTIFY bzd_flux_tasks"
+                ))
+            );
+            assert_eq!(log[0].statements().len(), 5);
+
+            Ok(())
+        }
+
+        // Устаревшее событие не ошибка: оно просто не применяется, а транзакция пачки
+        // коммитится, чтобы остальные события пачки не откатились вместе с ним
+        #[tokio::test]
+        async fn test_ok_handle_topics_users_stale_delete_with_retract() -> Result<(), Error> {
+            let req = request(Type::Deleted);
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            service::handle_topics_users(&db, &retract(), vec![req]).await?;
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 1);
+            assert_eq!(
+                log[0].statements().last(),
+                Some(&Statement::from_string(DatabaseBackend::Postgres, "COMMIT"))
+            );
+            assert_eq!(log[0].statements().len(), 3);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_handle_topics_users_batch() -> Result<(), Error> {
+            let created = request(Type::Created);
+            let deleted = request(Type::Deleted);
+            // Более позднее событие той же подписки в той же пачке вытесняет предыдущее
+            let redeleted = Request {
+                tp: Type::Deleted,
+                event_seq: 2,
+                ..created.clone()
+            };
+
+            let settings = FeedsSettings {
+                limits: LimitsSettings {
+                    backfill: 0,
+                    ..FeedsSettings::stub().limits
+                },
+                ..FeedsSettings::stub()
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[
+                    TopicUserModel::from(redeleted.clone()),
+                    TopicUserModel::from(deleted.clone()),
+                ]])
+                .into_connection();
+
+            service::handle_topics_users(&db, &settings, vec![created, deleted, redeleted]).await?;
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 1);
+            assert!(log[0].statements().get(1).is_some_and(|it| {
+                it.sql
+                    .contains("($9, $10, $11, $12, $13, $14, $15, $16) ON CONFLICT")
+                    && !it.sql.contains("$17")
+            }));
+            assert_eq!(log[0].statements().len(), 3);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_handle_topics_users_create() -> Result<(), Error> {
+            let req = request(Type::Created);
+
+            let settings = FeedsSettings::stub();
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[TopicUserModel::from(req.clone())]])
+                .append_exec_results([
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 1,
+                    },
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 0,
+                    },
+                ])
+                .into_connection();
+
+            service::handle_topics_users(&db, &settings, vec![req]).await?;
+
+            let log = db.into_transaction_log();
+
+            assert!(
+                log[0]
+                    .statements()
+                    .get(2)
+                    .is_some_and(|it| it.sql.starts_with(r#"INSERT INTO "tasks""#))
+            );
+            assert_eq!(log[0].statements().len(), 5);
+
+            // assert_eq!(
+            //     db.into_transaction_log(),
+            //     [Transaction::from_sql_and_values(
+            //         DatabaseBackend::Postgres,
+            //         r#"
+            //             INSERT INTO "topics_users" ("topic_user_id", "user_id", "topic_id", "created_at", "updated_at")
+            //             VALUES ($1, $2, $3, $4, $5) ON CONFLICT ("topic_user_id")
+            //             DO NOTHING RETURNING "topic_user_id"
+            //         "#,
+            //         [
+            //             req.topic_user_id.into(),
+            //             req.user_id.into(),
+            //             req.topic_id.into(),
+            //             ???,
+            //             ???
+            //         ]
+            //     ),]
+            // );
+
+            Ok(())
+        }
+    }
+}
+
+pub async fn get_user_entries(
+    db: &DbConn,
+    settings: &FeedsSettings,
+    req: get_user_entries::Request,
+) -> Result<get_user_entries::Response, AppError> {
+    use get_user_entries::Direction;
+
+    req.validate_with_args(&settings.pagination)?;
+
+    // Меньше минимума отклоняется валидацией, больше максимума — прижимается
+    let limit = req.page_size.unwrap_or(settings.limits.user).clamp(
+        settings.pagination.min_page_size,
+        settings.pagination.max_page_size,
+    );
+    let key = &settings.pagination.token_key;
+
+    // Позиция и направление берутся только из подписанного токена; без токена — первая страница
+    let (direction, cursor_entry_id) = match req.page_token.as_deref() {
+        Some(token) => {
+            let token = page_token::decode(key, token)?;
+
+            if token.user_id != req.user_id || token.filters != req.filters {
+                return Err(AppError::InvalidPageToken);
+            }
+
+            (token.direction, Some(token.entry_id))
+        }
+        None => (req.direction, None),
+    };
+
+    let filter_topic_ids =
+        (!req.filters.topic_ids.is_empty()).then(|| req.filters.topic_ids.clone());
+
+    // id записей генерируются до коммита и коммитятся не по порядку, поэтому токен более новых
+    // указывает на окно раньше головы: запись, закоммиченная позже с меньшим id, не теряется.
+    // Записи из окна приходят повторно, клиент дедуплицирует их по entry_id
+    let window = TimeDelta::seconds(
+        i64::try_from(settings.streaming.reorder_window_secs).map_err(|_| AppError::Unreachable)?,
+    );
+
+    // Более новые без позиции — это то, что появится после текущей головы ленты
+    let (cursor_entry_id, newer_floor) = match (direction, cursor_entry_id) {
+        (Direction::Newer, None) => {
+            let head_entry_id =
+                get_head_entry_id(db, settings, req.user_id, filter_topic_ids.clone()).await?;
+
+            (
+                Some(head_entry_id),
+                Some(repo::entry::entry_id_before(head_entry_id, window)),
+            )
+        }
+        (Direction::Newer, cursor_entry_id) => (cursor_entry_id, cursor_entry_id),
+        (Direction::Older, cursor_entry_id) => (cursor_entry_id, None),
+    };
+
+    let mut entries = match direction {
+        Direction::Older => {
+            repo::get_entries_by_user_id(
+                db,
+                req.user_id,
+                filter_topic_ids.clone(),
+                cursor_entry_id,
+                limit + 1,
+            )
+            .await?
+        }
+        Direction::Newer => {
+            repo::get_entries_after_entry_id(
+                db,
+                req.user_id,
+                filter_topic_ids.clone(),
+                cursor_entry_id,
+                limit + 1,
+            )
+            .await?
+        }
+    };
+
+    let topics_users = repo::get_fanout_topics_users_by_user_id(
+        db,
+        req.user_id,
+        filter_topic_ids,
+        settings.fanout.max_topics,
+    )
+    .await?;
+
+    if !topics_users.is_empty() {
+        let messages_topics = match direction {
+            Direction::Older => {
+                repo::get_messages_topics_fanout_on_read(
+                    db,
+                    &topics_users,
+                    cursor_entry_id,
+                    limit + 1,
+                )
+                .await?
+            }
+            Direction::Newer => {
+                repo::get_messages_topics_fanout_on_read_after_entry_id(
+                    db,
+                    &topics_users,
+                    cursor_entry_id,
+                    limit + 1,
+                )
+                .await?
+            }
+        };
+
+        entries.extend(messages_topics.into_iter().filter_map(|message_topic| {
+            topics_users
+                .iter()
+                .find(|it| it.topic_id == message_topic.topic_id)
+                .map(|topic_user| EntryModel::from_message_topic(message_topic, topic_user))
+        }));
+
+        match direction {
+            Direction::Older => entries.sort_by_key(|it| Reverse(it.entry_id)),
+            Direction::Newer => entries.sort_by_key(|it| it.entry_id),
+        }
+
+        let mut message_ids = HashSet::new();
+        entries.retain(|it| message_ids.insert(it.message_id));
+
+        entries.truncate(usize::try_from(limit + 1).map_err(|_| AppError::Unreachable)?);
+    }
+
+    let has_more = entries.len() > usize::try_from(limit).map_err(|_| AppError::Unreachable)?;
+    let extra_entry = if has_more { entries.pop() } else { None };
+
+    // head ведёт к более новым записям (строго новее), tail — к более старым (включительно).
+    // Пока есть ещё страницы, head продолжает чтение с последней записи, а дойдя до конца,
+    // отступает на окно, но не раньше прежней позиции
+    let (head_entry_id, tail_entry_id) = match direction {
+        Direction::Older => (
+            entries
+                .first()
+                .map(|it| repo::entry::entry_id_before(it.entry_id, window)),
+            extra_entry.map(|it| it.entry_id),
+        ),
+        Direction::Newer if has_more => (entries.last().map(|it| it.entry_id), cursor_entry_id),
+        Direction::Newer => (
+            entries
+                .last()
+                .map(|it| repo::entry::entry_id_before(it.entry_id, window))
+                .max(newer_floor),
+            cursor_entry_id,
+        ),
+    };
+
+    let issue = |direction, entry_id: Option<Uuid>| {
+        entry_id
+            .map(|entry_id| {
+                page_token::encode(
+                    key,
+                    &PageToken {
+                        user_id: req.user_id,
+                        direction,
+                        entry_id,
+                        filters: req.filters.clone(),
+                    },
+                )
+            })
+            .transpose()
+    };
+
+    let head_page_token = issue(Direction::Newer, head_entry_id)?;
+    let tail_page_token = issue(Direction::Older, tail_entry_id)?;
+
+    // Топики нужны только для подписок из самой страницы
+    let topic_user_ids: BTreeSet<_> = entries
+        .iter()
+        .flat_map(|it| it.topic_user_ids.iter().copied())
+        .collect();
+
+    let topic_ids = if topic_user_ids.is_empty() {
+        HashMap::new()
+    } else {
+        repo::get_topics_users_by_topic_user_ids(db, topic_user_ids.into_iter().collect())
+            .await?
+            .into_iter()
+            .map(|it| (it.topic_user_id, it.topic_id))
+            .collect()
+    };
+
+    Ok(get_user_entries::Response {
+        entries,
+        head_page_token,
+        tail_page_token,
+        has_more,
+        topic_ids,
+    })
+}
+
+// Самый новый entry_id ленты среди записей и сообщений крупных топиков; у пустой ленты — nil
+async fn get_head_entry_id(
+    db: &DbConn,
+    settings: &FeedsSettings,
+    user_id: Uuid,
+    filter_topic_ids: Option<Vec<Uuid>>,
+) -> Result<Uuid, AppError> {
+    let entries =
+        repo::get_entries_by_user_id(db, user_id, filter_topic_ids.clone(), None, 1).await?;
+
+    let topics_users = repo::get_fanout_topics_users_by_user_id(
+        db,
+        user_id,
+        filter_topic_ids,
+        settings.fanout.max_topics,
+    )
+    .await?;
+
+    let messages_topics = if topics_users.is_empty() {
+        vec![]
+    } else {
+        repo::get_messages_topics_fanout_on_read(db, &topics_users, None, 1).await?
+    };
+
+    Ok(entries
+        .iter()
+        .map(|it| it.entry_id)
+        .chain(messages_topics.iter().map(|it| it.entry_id))
+        .max()
+        .unwrap_or_default())
+}
+
+pub mod get_user_entries {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Serialize};
+    use uuid::Uuid;
+    use validator::{Validate, ValidationError};
+
+    use crate::app::feeds::{repo::EntryModel, settings::PaginationSettings};
+
+    #[derive(Clone, Validate)]
+    #[validate(context = PaginationSettings)]
+    pub struct Request {
+        pub user_id: Uuid,
+        pub page_token: Option<String>,
+        // Направление первой страницы; дальше оно зашито в токен
+        pub direction: Direction,
+        #[validate(custom(function = "validate_filters", use_context))]
+        pub filters: Filters,
+        // Без размера — limits.user; границы берутся из настроек пагинации
+        #[validate(custom(function = "validate_page_size", use_context))]
+        pub page_size: Option<u64>,
+    }
+
+    fn validate_page_size(
+        page_size: u64,
+        settings: &PaginationSettings,
+    ) -> Result<(), ValidationError> {
+        if page_size < settings.min_page_size {
+            return Err(ValidationError::new("range"));
+        }
+
+        Ok(())
+    }
+
+    fn validate_filters(
+        filters: &Filters,
+        settings: &PaginationSettings,
+    ) -> Result<(), ValidationError> {
+        if filters.topic_ids.len() > settings.max_filter_topic_ids {
+            return Err(ValidationError::new("length"));
+        }
+
+        Ok(())
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Direction {
+        // От курсора к более старым, курсор включительно
+        #[default]
+        Older,
+        // От курсора к более новым по возрастанию, курсор не включается;
+        // без курсора — от текущей головы ленты
+        Newer,
+    }
+
+    // Параметры фильтрации ленты; токен страницы действителен только с теми же фильтрами
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Filters {
+        // Пустой список — все топики
+        pub topic_ids: Vec<Uuid>,
+    }
+
+    impl Filters {
+        // Порядок и повторы не должны менять фильтр, иначе токен перестанет подходить
+        pub fn new(mut topic_ids: Vec<Uuid>) -> Self {
+            topic_ids.sort();
+            topic_ids.dedup();
+
+            Self { topic_ids }
+        }
+    }
+
+    pub struct Response {
+        pub entries: Vec<EntryModel>,
+        pub head_page_token: Option<String>,
+        pub tail_page_token: Option<String>,
+        pub has_more: bool,
+        // topic_user_id -> topic_id для подписок из записей страницы
+        pub topic_ids: HashMap<Uuid, Uuid>,
+    }
+
+    impl Response {
+        pub fn entry_topic_ids(&self, entry: &EntryModel) -> Vec<Uuid> {
+            entry
+                .topic_user_ids
+                .iter()
+                .filter_map(|it| self.topic_ids.get(it).copied())
+                .collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use chrono::{NaiveDateTime, TimeDelta, Utc};
+        use sea_orm::{DatabaseBackend, MockDatabase, Transaction};
+        use uuid::Uuid;
+
+        use crate::app::error::AppError;
+        use crate::app::feeds::{
+            page_token::{self, PageToken},
+            repo::{EntryModel, MessageTopicModel, TopicUserModel, entry},
+            service::{
+                self,
+                get_user_entries::{Direction, Filters, Request},
+            },
+            settings::{FeedsSettings, LimitsSettings},
+        };
+
+        #[tokio::test]
+        async fn test_ok_get_without_cursor() -> Result<(), Error> {
+            let entries = vec![EntryModel::stub(); 5];
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([entries.clone()])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: None,
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let settings = test_settings(4);
+
+            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+
+            assert_eq!(res.entries.len(), 4);
+            assert_eq!(res.entries.first(), entries.first());
+            assert!(res.has_more);
+            assert_eq!(
+                test_entry_id(res.tail_page_token.as_deref())?,
+                entries.last().map(|it| it.entry_id)
+            );
+
+            assert_eq!(
+                db.into_transaction_log(),
+                [
+                    Transaction::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 ORDER BY "entries"."entry_id" DESC LIMIT $2"#,
+                        [req.user_id.into(), (settings.limits.user + 1).into()]
+                    ),
+                    Transaction::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"SELECT "topics_users"."topic_user_id", "topics_users"."user_id", "topics_users"."topic_id", "topics_users"."event_at", "topics_users"."event_seq", "topics_users"."deleted_at", "topics_users"."created_at", "topics_users"."updated_at" FROM "topics_users" WHERE "topics_users"."user_id" = $1 AND "topics_users"."deleted_at" IS NULL AND "topics_users"."topic_id" IN (SELECT "topic_id" FROM "topics" WHERE "topics"."fanout_on_read" = $2) ORDER BY "topics_users"."topic_user_id" DESC LIMIT $3"#,
+                        [
+                            req.user_id.into(),
+                            true.into(),
+                            settings.fanout.max_topics.into()
+                        ]
+                    ),
+                ]
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_without_cursor_and_last() -> Result<(), Error> {
+            let entries = vec![EntryModel::stub(); 4];
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([entries.clone()])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: None,
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let settings = test_settings(4);
+
+            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+
+            assert_eq!(res.entries.len(), 4);
+            assert_eq!(res.entries.first(), entries.first());
+            assert!(!res.has_more);
+            assert_eq!(res.tail_page_token, None);
+
+            assert_eq!(
+                db.into_transaction_log(),
+                [
+                    Transaction::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 ORDER BY "entries"."entry_id" DESC LIMIT $2"#,
+                        [req.user_id.into(), (settings.limits.user + 1).into()]
+                    ),
+                    Transaction::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"SELECT "topics_users"."topic_user_id", "topics_users"."user_id", "topics_users"."topic_id", "topics_users"."event_at", "topics_users"."event_seq", "topics_users"."deleted_at", "topics_users"."created_at", "topics_users"."updated_at" FROM "topics_users" WHERE "topics_users"."user_id" = $1 AND "topics_users"."deleted_at" IS NULL AND "topics_users"."topic_id" IN (SELECT "topic_id" FROM "topics" WHERE "topics"."fanout_on_read" = $2) ORDER BY "topics_users"."topic_user_id" DESC LIMIT $3"#,
+                        [
+                            req.user_id.into(),
+                            true.into(),
+                            settings.fanout.max_topics.into()
+                        ]
+                    ),
+                ]
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_with_cursor() -> Result<(), Error> {
+            let entries = vec![EntryModel::stub(); 5];
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([entries.clone()])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let user_id = Uuid::now_v7();
+            let cursor_entry_id = Uuid::now_v7();
+
+            let req = Request {
+                user_id,
+                page_token: Some(test_page_token(user_id, Direction::Older, cursor_entry_id)?),
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let settings = test_settings(4);
+
+            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+
+            assert_eq!(res.entries.len(), 4);
+            assert_eq!(res.entries.first(), entries.first());
+            assert!(res.has_more);
+            assert_eq!(
+                test_entry_id(res.tail_page_token.as_deref())?,
+                entries.last().map(|it| it.entry_id)
+            );
+
+            assert_eq!(
+                db.into_transaction_log(),
+                [
+                    Transaction::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 AND "entries"."entry_id" <= $2 ORDER BY "entries"."entry_id" DESC LIMIT $3"#,
+                        [
+                            req.user_id.into(),
+                            cursor_entry_id.into(),
+                            (settings.limits.user + 1).into()
+                        ]
+                    ),
+                    Transaction::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"SELECT "topics_users"."topic_user_id", "topics_users"."user_id", "topics_users"."topic_id", "topics_users"."event_at", "topics_users"."event_seq", "topics_users"."deleted_at", "topics_users"."created_at", "topics_users"."updated_at" FROM "topics_users" WHERE "topics_users"."user_id" = $1 AND "topics_users"."deleted_at" IS NULL AND "topics_users"."topic_id" IN (SELECT "topic_id" FROM "topics" WHERE "topics"."fanout_on_read" = $2) ORDER BY "topics_users"."topic_user_id" DESC LIMIT $3"#,
+                        [
+                            req.user_id.into(),
+                            true.into(),
+                            settings.fanout.max_topics.into()
+                        ]
+                    ),
+                ]
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_with_cursor_and_last() -> Result<(), Error> {
+            let entries = vec![EntryModel::stub(); 4];
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([entries.clone()])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let user_id = Uuid::now_v7();
+            let cursor_entry_id = Uuid::now_v7();
+
+            let req = Request {
+                user_id,
+                page_token: Some(test_page_token(user_id, Direction::Older, cursor_entry_id)?),
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let settings = test_settings(4);
+
+            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+
+            assert_eq!(res.entries.len(), 4);
+            assert_eq!(res.entries.first(), entries.first());
+            assert!(!res.has_more);
+            assert_eq!(res.tail_page_token, None);
+
+            assert_eq!(
+                db.into_transaction_log(),
+                [
+                    Transaction::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 AND "entries"."entry_id" <= $2 ORDER BY "entries"."entry_id" DESC LIMIT $3"#,
+                        [
+                            req.user_id.into(),
+                            cursor_entry_id.into(),
+                            (settings.limits.user + 1).into()
+                        ]
+                    ),
+                    Transaction::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"SELECT "topics_users"."topic_user_id", "topics_users"."user_id", "topics_users"."topic_id", "topics_users"."event_at", "topics_users"."event_seq", "topics_users"."deleted_at", "topics_users"."created_at", "topics_users"."updated_at" FROM "topics_users" WHERE "topics_users"."user_id" = $1 AND "topics_users"."deleted_at" IS NULL AND "topics_users"."topic_id" IN (SELECT "topic_id" FROM "topics" WHERE "topics"."fanout_on_read" = $2) ORDER BY "topics_users"."topic_user_id" DESC LIMIT $3"#,
+                        [
+                            req.user_id.into(),
+                            true.into(),
+                            settings.fanout.max_topics.into()
+                        ]
+                    ),
+                ]
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_with_fanout_on_read() -> Result<(), Error> {
+            let older = EntryModel::stub();
+            let newer = EntryModel::stub();
+            let entries = vec![newer, older];
+
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: None,
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let topic_user = TopicUserModel::new(Uuid::now_v7(), req.user_id, Uuid::now_v7());
+
+            let mut message_topic = MessageTopicModel::new(
+                Uuid::now_v7(),
+                Uuid::now_v7(),
+                topic_user.topic_id,
+                (Utc::now() + TimeDelta::seconds(1)).naive_utc(),
+            );
+            message_topic.fanout_on_read = true;
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([entries.clone()])
+                .append_query_results([[topic_user.clone()]])
+                .append_query_results([[message_topic.clone()]])
+                .append_query_results([[topic_user.clone()]])
+                .into_connection();
+
+            let settings = test_settings(2);
+
+            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+
+            assert_eq!(res.entries.len(), 2);
+            assert_eq!(res.entries[0].entry_id, message_topic.entry_id);
+            assert_eq!(res.entries[0].message_id, message_topic.message_id);
+            assert_eq!(
+                res.entries[0].topic_user_ids,
+                vec![topic_user.topic_user_id]
+            );
+            assert_eq!(
+                res.entry_topic_ids(&res.entries[0]),
+                vec![topic_user.topic_id]
+            );
+            assert_eq!(res.entries.get(1), entries.first());
+            assert!(res.has_more);
+            assert_eq!(
+                test_entry_id(res.tail_page_token.as_deref())?,
+                entries.last().map(|it| it.entry_id)
+            );
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(
+                log[2],
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "messages_topics"."message_topic_id", "messages_topics"."message_id", "messages_topics"."topic_id", "messages_topics"."entry_id", "messages_topics"."published_at", "messages_topics"."fanout_on_read", "messages_topics"."created_at", "messages_topics"."updated_at" FROM "messages_topics" WHERE "messages_topics"."topic_id" = $1 AND "messages_topics"."published_at" >= $2 AND "messages_topics"."fanout_on_read" = $3 ORDER BY "messages_topics"."entry_id" DESC LIMIT $4"#,
+                    [
+                        topic_user.topic_id.into(),
+                        topic_user.event_at.into(),
+                        true.into(),
+                        (settings.limits.user + 1).into()
+                    ]
+                )
+            );
+            assert_eq!(
+                log[3],
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "topics_users"."topic_user_id", "topics_users"."user_id", "topics_users"."topic_id", "topics_users"."event_at", "topics_users"."event_seq", "topics_users"."deleted_at", "topics_users"."created_at", "topics_users"."updated_at" FROM "topics_users" WHERE "topics_users"."topic_user_id" IN ($1)"#,
+                    [topic_user.topic_user_id.into()]
+                )
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_with_unsubscribed_topic() -> Result<(), Error> {
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: None,
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            // Отписка при политике keep: запись осталась, подписка — tombstone
+            let topic_user = TopicUserModel {
+                deleted_at: Some(Utc::now().naive_utc()),
+                ..TopicUserModel::new(Uuid::now_v7(), req.user_id, Uuid::now_v7())
+            };
+            let entry = EntryModel {
+                topic_user_ids: vec![topic_user.topic_user_id],
+                ..EntryModel::stub()
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[entry.clone()]])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .append_query_results([[topic_user.clone()]])
+                .into_connection();
+
+            let res = service::get_user_entries(&db, &test_settings(2), req).await?;
+
+            assert_eq!(res.entries, vec![entry.clone()]);
+            assert_eq!(res.entry_topic_ids(&entry), vec![topic_user.topic_id]);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_newer() -> Result<(), Error> {
+            let older = EntryModel::stub();
+            let newer = EntryModel::stub();
+            let newest = EntryModel::stub();
+            let entries = vec![older, newer, newest];
+
+            let user_id = Uuid::now_v7();
+            let cursor_entry_id = Uuid::now_v7();
+
+            let req = Request {
+                user_id,
+                page_token: Some(test_page_token(user_id, Direction::Newer, cursor_entry_id)?),
+                direction: Direction::Newer,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([entries.clone()])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let settings = test_settings(2);
+
+            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+
+            assert_eq!(res.entries, entries[..2]);
+            assert!(res.has_more);
+            assert_eq!(
+                test_entry_id(res.head_page_token.as_deref())?,
+                Some(entries[1].entry_id)
+            );
+            assert_eq!(
+                test_entry_id(res.tail_page_token.as_deref())?,
+                Some(cursor_entry_id)
+            );
+
+            assert_eq!(
+                db.into_transaction_log().first(),
+                Some(&Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 AND "entries"."entry_id" > $2 ORDER BY "entries"."entry_id" ASC LIMIT $3"#,
+                    [
+                        req.user_id.into(),
+                        cursor_entry_id.into(),
+                        (settings.limits.user + 1).into()
+                    ]
+                ))
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_newer_steps_head_back_a_window() -> Result<(), Error> {
+            let user_id = Uuid::now_v7();
+            let cursor_entry_id = entry::entry_id_at(Utc::now().naive_utc() - TimeDelta::hours(1));
+
+            let entry = EntryModel::stub();
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[entry.clone()]])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let req = Request {
+                user_id,
+                page_token: Some(test_page_token(user_id, Direction::Newer, cursor_entry_id)?),
+                direction: Direction::Newer,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let res = service::get_user_entries(&db, &test_settings(2), req).await?;
+
+            assert_eq!(res.entries, vec![entry.clone()]);
+            assert!(!res.has_more);
+            // Запись, закоммиченная позже с id из окна, придёт при следующем обновлении
+            assert_eq!(
+                test_entry_time(res.head_page_token.as_deref())?,
+                test_window_before(entry.entry_id)
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_newer_keeps_head_at_cursor() -> Result<(), Error> {
+            let user_id = Uuid::now_v7();
+            let cursor_entry_id = entry::entry_id_at(Utc::now().naive_utc() - TimeDelta::hours(1));
+
+            // Новее курсора, но окно назад от неё ушло бы раньше курсора
+            let entry = EntryModel {
+                entry_id: entry::entry_id_at(
+                    Utc::now().naive_utc() - TimeDelta::hours(1) + TimeDelta::seconds(1),
+                ),
+                ..EntryModel::stub()
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[entry.clone()]])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let req = Request {
+                user_id,
+                page_token: Some(test_page_token(user_id, Direction::Newer, cursor_entry_id)?),
+                direction: Direction::Newer,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let res = service::get_user_entries(&db, &test_settings(2), req).await?;
+
+            assert_eq!(
+                test_entry_id(res.head_page_token.as_deref())?,
+                Some(cursor_entry_id)
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_newer_with_fanout_on_read() -> Result<(), Error> {
+            let user_id = Uuid::now_v7();
+            let cursor_entry_id = Uuid::now_v7();
+
+            let req = Request {
+                user_id,
+                page_token: Some(test_page_token(user_id, Direction::Newer, cursor_entry_id)?),
+                direction: Direction::Newer,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let topic_user = TopicUserModel::new(Uuid::now_v7(), user_id, Uuid::now_v7());
+
+            let older = EntryModel::stub();
+            let message_topic = MessageTopicModel {
+                entry_id: Uuid::now_v7(),
+                fanout_on_read: true,
+                ..MessageTopicModel::new(
+                    Uuid::now_v7(),
+                    Uuid::now_v7(),
+                    topic_user.topic_id,
+                    Utc::now().naive_utc(),
+                )
+            };
+            let newer = EntryModel::stub();
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[older.clone(), newer]])
+                .append_query_results([[topic_user.clone()]])
+                .append_query_results([[message_topic.clone()]])
+                .append_query_results([[topic_user.clone()]])
+                .into_connection();
+
+            let res = service::get_user_entries(&db, &test_settings(2), req).await?;
+
+            assert_eq!(
+                res.entries.iter().map(|it| it.entry_id).collect::<Vec<_>>(),
+                vec![older.entry_id, message_topic.entry_id]
+            );
+            assert!(res.has_more);
+            assert_eq!(
+                test_entry_id(res.head_page_token.as_deref())?,
+                Some(message_topic.entry_id)
+            );
+
+            let log = db.into_transaction_log();
+
+            assert!(log[2].statements()[0].sql.contains(
+                r#""messages_topics"."entry_id" > $4 ORDER BY "messages_topics"."entry_id" ASC"#
+            ));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_newer_without_cursor() -> Result<(), Error> {
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: None,
+                direction: Direction::Newer,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let topic_user = TopicUserModel::new(Uuid::now_v7(), req.user_id, Uuid::now_v7());
+
+            let entry = EntryModel::stub();
+            // Голова ленты — сообщение крупного топика, а не последняя запись
+            let message_topic = MessageTopicModel {
+                entry_id: Uuid::now_v7(),
+                fanout_on_read: true,
+                ..MessageTopicModel::new(
+                    Uuid::now_v7(),
+                    Uuid::now_v7(),
+                    topic_user.topic_id,
+                    Utc::now().naive_utc(),
+                )
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[entry]])
+                .append_query_results([[topic_user.clone()]])
+                .append_query_results([[message_topic.clone()]])
+                .append_query_results([Vec::<EntryModel>::new()])
+                .append_query_results([[topic_user.clone()]])
+                .append_query_results([Vec::<MessageTopicModel>::new()])
+                .into_connection();
+
+            let res = service::get_user_entries(&db, &test_settings(2), req.clone()).await?;
+
+            assert!(res.entries.is_empty());
+            assert!(!res.has_more);
+            // Следующее обновление перечитает окно перед головой
+            assert_eq!(
+                test_entry_time(res.head_page_token.as_deref())?,
+                test_window_before(message_topic.entry_id)
+            );
+            assert_eq!(
+                test_entry_id(res.tail_page_token.as_deref())?,
+                Some(message_topic.entry_id)
+            );
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(
+                log[0],
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 ORDER BY "entries"."entry_id" DESC LIMIT $2"#,
+                    [req.user_id.into(), 1u64.into()]
+                )
+            );
+            assert_eq!(
+                log[3],
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 AND "entries"."entry_id" > $2 ORDER BY "entries"."entry_id" ASC LIMIT $3"#,
+                    [
+                        req.user_id.into(),
+                        message_topic.entry_id.into(),
+                        3u64.into()
+                    ]
+                )
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_newer_without_new_entries() -> Result<(), Error> {
+            let user_id = Uuid::now_v7();
+            let cursor_entry_id = Uuid::now_v7();
+
+            let req = Request {
+                user_id,
+                page_token: Some(test_page_token(user_id, Direction::Newer, cursor_entry_id)?),
+                direction: Direction::Newer,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<EntryModel>::new()])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let res = service::get_user_entries(&db, &test_settings(2), req.clone()).await?;
+
+            assert!(res.entries.is_empty());
+            assert!(!res.has_more);
+            assert_eq!(
+                test_entry_id(res.head_page_token.as_deref())?,
+                Some(cursor_entry_id)
+            );
+            assert_eq!(
+                test_entry_id(res.tail_page_token.as_deref())?,
+                Some(cursor_entry_id)
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_issues_tokens_for_both_directions() -> Result<(), Error> {
+            let entries = vec![EntryModel::stub(), EntryModel::stub(), EntryModel::stub()];
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([entries.clone()])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: None,
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let settings = test_settings(2);
+            let key = &settings.pagination.token_key;
+
+            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+
+            let head = page_token::decode(key, res.head_page_token.as_deref().unwrap_or_default())?;
+            let tail = page_token::decode(key, res.tail_page_token.as_deref().unwrap_or_default())?;
+
+            assert_eq!(head.user_id, req.user_id);
+            assert_eq!(head.direction, Direction::Newer);
+            assert_eq!(
+                entry::entry_time(head.entry_id),
+                test_window_before(entries[0].entry_id)
+            );
+            assert_eq!(tail.user_id, req.user_id);
+            assert_eq!(tail.direction, Direction::Older);
+            assert_eq!(tail.entry_id, entries[2].entry_id);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_err_get_with_token_of_other_user() -> Result<(), Error> {
+            let db = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
+
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: Some(test_page_token(
+                    Uuid::now_v7(),
+                    Direction::Older,
+                    Uuid::now_v7(),
+                )?),
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: None,
+            };
+
+            let res = service::get_user_entries(&db, &test_settings(2), req).await;
+
+            assert!(matches!(res, Err(AppError::InvalidPageToken)));
+            assert!(db.into_transaction_log().is_empty());
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_with_page_size() -> Result<(), Error> {
+            let settings = test_settings(4);
+
+            for (page_size, limit) in [
+                (Some(5), 5),
+                (None, settings.limits.user),
+                (Some(1000), settings.pagination.max_page_size),
+            ] {
+                let db = MockDatabase::new(DatabaseBackend::Postgres)
+                    .append_query_results([Vec::<EntryModel>::new()])
+                    .append_query_results([Vec::<TopicUserModel>::new()])
+                    .into_connection();
+
+                let req = Request {
+                    user_id: Uuid::now_v7(),
+                    page_token: None,
+                    direction: Direction::Older,
+                    filters: Filters::default(),
+                    page_size,
+                };
+
+                service::get_user_entries(&db, &settings, req.clone()).await?;
+
+                assert_eq!(
+                    db.into_transaction_log().first(),
+                    Some(&Transaction::from_sql_and_values(
+                        DatabaseBackend::Postgres,
+                        r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 ORDER BY "entries"."entry_id" DESC LIMIT $2"#,
+                        [req.user_id.into(), (limit + 1).into()]
+                    ))
+                );
+            }
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_err_get_with_zero_page_size() -> Result<(), Error> {
+            let db = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
+
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: None,
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: Some(0),
+            };
+
+            let res = service::get_user_entries(&db, &test_settings(4), req).await;
+
+            assert!(matches!(res, Err(AppError::Validation(_))));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_err_get_with_page_size_below_min() -> Result<(), Error> {
+            let db = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
+
+            let mut settings = test_settings(10);
+            settings.pagination.min_page_size = 5;
+
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: None,
+                direction: Direction::Older,
+                filters: Filters::default(),
+                page_size: Some(3),
+            };
+
+            let res = service::get_user_entries(&db, &settings, req).await;
+
+            assert!(matches!(res, Err(AppError::Validation(_))));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_err_get_with_too_many_topic_ids() -> Result<(), Error> {
+            let db = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
+
+            let settings = test_settings(4);
+
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                page_token: None,
+                direction: Direction::Older,
+                filters: Filters::new(
+                    (0..=settings.pagination.max_filter_topic_ids)
+                        .map(|_| Uuid::now_v7())
+                        .collect(),
+                ),
+                page_size: None,
+            };
+
+            let res = service::get_user_entries(&db, &settings, req).await;
+
+            assert!(matches!(res, Err(AppError::Validation(_))));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_with_topic_filter() -> Result<(), Error> {
+            let user_id = Uuid::now_v7();
+            let topic_user = TopicUserModel::new(Uuid::now_v7(), user_id, Uuid::now_v7());
+
+            let mut entries = vec![EntryModel::stub(), EntryModel::stub(), EntryModel::stub()];
+            entries.reverse();
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([entries.clone()])
+                .append_query_results([[topic_user.clone()]])
+                .append_query_results([Vec::<MessageTopicModel>::new()])
+                .into_connection();
+
+            let req = Request {
+                user_id,
+                page_token: None,
+                direction: Direction::Older,
+                filters: Filters::new(vec![topic_user.topic_id, topic_user.topic_id]),
+                page_size: None,
+            };
+
+            let settings = test_settings(2);
+
+            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+
+            assert_eq!(res.entries, entries[..2]);
+
+            let tail = page_token::decode(
+                &settings.pagination.token_key,
+                res.tail_page_token.as_deref().unwrap_or_default(),
+            )?;
+
+            assert_eq!(tail.filters.topic_ids, vec![topic_user.topic_id]);
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(
+                log.first(),
+                Some(&Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 AND (entries.topic_user_ids && array(select topics_users.topic_user_id from topics_users where topics_users.user_id = $2 and topics_users.topic_id = any($3))) ORDER BY "entries"."entry_id" DESC LIMIT $4"#,
+                    [
+                        user_id.into(),
+                        user_id.into(),
+                        vec![topic_user.topic_id].into(),
+                        (settings.limits.user + 1).into()
+                    ]
+                ))
+            );
+            assert_eq!(
+                log[1],
+                Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "topics_users"."topic_user_id", "topics_users"."user_id", "topics_users"."topic_id", "topics_users"."event_at", "topics_users"."event_seq", "topics_users"."deleted_at", "topics_users"."created_at", "topics_users"."updated_at" FROM "topics_users" WHERE "topics_users"."user_id" = $1 AND "topics_users"."deleted_at" IS NULL AND "topics_users"."topic_id" IN (SELECT "topic_id" FROM "topics" WHERE "topics"."fanout_on_read" = $2) AND "topics_users"."topic_id" IN ($3) ORDER BY "topics_users"."topic_user_id" DESC LIMIT $4"#,
+                    [
+                        user_id.into(),
+                        true.into(),
+                        topic_user.topic_id.into(),
+                        settings.fanout.max_topics.into()
+                    ]
+                )
+            );
+            assert_eq!(
+                log.last(),
+                Some(&Transaction::from_sql_and_values(
+                    DatabaseBackend::Postgres,
+                    r#"SELECT "messages_topics"."message_topic_id", "messages_topics"."message_id", "messages_topics"."topic_id", "messages_topics"."entry_id", "messages_topics"."published_at", "messages_topics"."fanout_on_read", "messages_topics"."created_at", "messages_topics"."updated_at" FROM "messages_topics" WHERE "messages_topics"."topic_id" = $1 AND "messages_topics"."published_at" >= $2 AND "messages_topics"."fanout_on_read" = $3 ORDER BY "messages_topics"."entry_id" DESC LIMIT $4"#,
+                    [
+                        topic_user.topic_id.into(),
+                        topic_user.event_at.into(),
+                        true.into(),
+                        (settings.limits.user + 1).into()
+                    ]
+                ))
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_err_get_with_stale_filters() -> Result<(), Error> {
+            let db = MockDatabase::new(DatabaseBackend::Postgres).into_connection();
+
+            let user_id = Uuid::now_v7();
+
+            let req = Request {
+                user_id,
+                page_token: Some(test_page_token(user_id, Direction::Older, Uuid::now_v7())?),
+                direction: Direction::Older,
+                filters: Filters::new(vec![Uuid::now_v7()]),
+                page_size: None,
+            };
+
+            let res = service::get_user_entries(&db, &test_settings(2), req).await;
+
+            assert!(matches!(res, Err(AppError::InvalidPageToken)));
+
+            Ok(())
+        }
+
+        fn test_page_token(
+            user_id: Uuid,
+            direction: Direction,
+            entry_id: Uuid,
+        ) -> Result<String, AppError> {
+            page_token::encode(
+                &FeedsSettings::stub().pagination.token_key,
+                &PageToken {
+                    user_id,
+                    direction,
+                    entry_id,
+                    filters: Filters::default(),
+                },
+            )
+        }
+
+        fn test_entry_id(token: Option<&str>) -> Result<Option<Uuid>, AppError> {
+            token
+                .map(|it| page_token::decode(&FeedsSettings::stub().pagination.token_key, it))
+                .transpose()
+                .map(|it| it.map(|it| it.entry_id))
+        }
+
+        fn test_entry_time(token: Option<&str>) -> Result<Option<NaiveDateTime>, AppError> {
+            Ok(test_entry_id(token)?.and_then(entry::entry_time))
+        }
+
+        fn test_window_before(entry_id: Uuid) -> Option<NaiveDateTime> {
+            let window = i64::try_from(FeedsSettings::stub().streaming.reorder_window_secs).ok()?;
+
+            entry::entry_time(entry_id).map(|at| at - TimeDelta::seconds(window))
+        }
+
+        fn test_settings(limit: u64) -> FeedsSettings {
+            FeedsSettings {
+                limits: LimitsSettings {
+                    user: limit,
+                    ..FeedsSettings::stub().limits
+                },
+                ..FeedsSettings::stub()
+            }
+        }
+    }
+}
+
+pub async fn subscribe_user_entries(
+    db: &DbConn,
+    settings: &FeedsSettings,
+    hub: &Hub,
+    req: subscribe_user_entries::Request,
+    tx: &mpsc::Sender<Result<EntryModel, AppError>>,
+) -> Result<(), AppError> {
+    // id записей генерируются до коммита, и параллельные задачи коммитят их не по порядку.
+    // Поэтому из базы перечитывается окно перед позицией, а повторы отсекаются по отправленным id
+    let window = TimeDelta::seconds(
+        i64::try_from(settings.streaming.reorder_window_secs).map_err(|_| AppError::Unreachable)?,
+    );
+    let window_start = || repo::entry::entry_id_at(Utc::now().naive_utc() - window);
+    let limit = usize::try_from(settings.limits.user).map_err(|_| AppError::Unreachable)?;
+
+    // Сообщения крупных топиков хаб собирает по подпискам, которые были на момент открытия потока
+    let topics_users =
+        repo::get_fanout_topics_users_by_user_id(db, req.user_id, None, settings.fanout.max_topics)
+            .await?;
+
+    // Подписка до чтения из базы, чтобы не потерять записи между ними
+    let mut rx = hub.subscribe(req.user_id, topics_users.clone());
+    let mut sent = BTreeSet::new();
+
+    // Без курсора история не отдаётся — только новые записи из хаба.
+    // С курсором часть окна может прийти повторно: клиент дедуплицирует по entry_id
+    let mut scan_from = req
+        .cursor_entry_id
+        .map(|it| repo::entry::entry_id_before(it, window));
+
+    loop {
+        if let Some(mut cursor_entry_id) = scan_from.take() {
+            sent = sent.split_off(&cursor_entry_id);
+
+            loop {
+                let mut entries = repo::get_entries_after_entry_id(
+                    db,
+                    req.user_id,
+                    None,
+                    Some(cursor_entry_id),
+                    settings.limits.user,
+                )
+                .await?;
+                let mut has_more = entries.len() >= limit;
+
+                if !topics_users.is_empty() {
+                    let messages_topics = repo::get_messages_topics_fanout_on_read_after_entry_id(
+                        db,
+                        &topics_users,
+                        Some(cursor_entry_id),
+                        settings.limits.user,
+                    )
+                    .await?;
+                    has_more |= messages_topics.len() >= limit;
+
+                    entries.extend(messages_topics.into_iter().filter_map(|message_topic| {
+                        topics_users
+                            .iter()
+                            .find(|it| it.topic_id == message_topic.topic_id)
+                            .map(|topic_user| {
+                                EntryModel::from_message_topic(message_topic, topic_user)
+                            })
+                    }));
+
+                    // Остаток страницы, что новее обрезанного, придёт следующим чтением
+                    entries.sort_by_key(|it| it.entry_id);
+                    entries.truncate(limit);
+                }
+
+                for entry in entries {
+                    cursor_entry_id = entry.entry_id;
+
+                    if !sent.insert(entry.entry_id) {
+                        continue;
+                    }
+
+                    if tx.send(Ok(entry)).await.is_err() {
+                        return Ok(());
+                    }
+                }
+
+                if !has_more {
+                    break;
+                }
+            }
+        }
+
+        loop {
+            let entry = select! {
+                _ = tx.closed() => return Ok(()),
+                entry = rx.recv() => entry,
+            };
+
+            match entry {
+                Ok(entry) => {
+                    // Старше окна перечитывать уже не будем, их id больше не нужны
+                    let window_start = window_start();
+                    sent = sent.split_off(&window_start);
+
+                    if entry.entry_id >= window_start && !sent.insert(entry.entry_id) {
+                        continue;
+                    }
+
+                    if tx.send(Ok(entry)).await.is_err() {
+                        return Ok(());
+                    }
+                }
+                // Отстали от хаба — перечитываем окно из базы
+                Err(RecvError::Lagged(_)) => {
+                    scan_from = Some(window_start());
+                    break;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+}
+
+pub mod subscribe_user_entries {
+    use uuid::Uuid;
+
+    #[derive(Clone)]
+    pub struct Request {
+        pub user_id: Uuid,
+        pub cursor_entry_id: Option<Uuid>,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use chrono::{TimeDelta, Utc};
+        use sea_orm::{DatabaseBackend, MockDatabase};
+        use tokio::sync::mpsc;
+        use uuid::Uuid;
+
+        use crate::app::feeds::{
+            hub::Hub,
+            repo::{EntryModel, MessageTopicModel, TopicUserModel, entry::entry_id_at},
+            service::{self, subscribe_user_entries::Request},
+            settings::FeedsSettings,
+        };
+
+        #[tokio::test]
+        async fn test_ok_subscribe_user_entries() -> Result<(), Error> {
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                cursor_entry_id: Some(Uuid::now_v7()),
+            };
+
+            let stored = EntryModel {
+                user_id: req.user_id,
+                ..EntryModel::stub()
+            };
+            let live = EntryModel {
+                user_id: req.user_id,
+                ..EntryModel::stub()
+            };
+            // Закоммичена позже, чем записи с бо́льшим id: всё равно должна дойти
+            let late = EntryModel {
+                user_id: req.user_id,
+                entry_id: entry_id_at(Utc::now().naive_utc() - TimeDelta::seconds(10)),
+                ..EntryModel::stub()
+            };
+            let other = EntryModel::stub();
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .append_query_results([[stored.clone()]])
+                .into_connection();
+
+            let hub = Hub::new(16);
+            let (tx, mut rx) = mpsc::channel(4);
+
+            let subscription = tokio::spawn({
+                let hub = hub.clone();
+
+                async move {
+                    service::subscribe_user_entries(&db, &FeedsSettings::stub(), &hub, req, &tx)
+                        .await
+                }
+            });
+
+            assert_eq!(rx.recv().await.transpose()?, Some(stored.clone()));
+
+            hub.publish(&[other, stored, late.clone(), live.clone()]);
+
+            assert_eq!(rx.recv().await.transpose()?, Some(late));
+            assert_eq!(rx.recv().await.transpose()?, Some(live));
+
+            drop(rx);
+
+            subscription.await??;
+
+            Ok(())
+        }
+
+        // Без курсора (SSE без Last-Event-ID) история из базы не отдаётся
+        #[tokio::test]
+        async fn test_ok_subscribe_user_entries_without_cursor() -> Result<(), Error> {
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                cursor_entry_id: None,
+            };
+
+            let live = EntryModel {
+                user_id: req.user_id,
+                ..EntryModel::stub()
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let hub = Hub::new(16);
+            let (tx, mut rx) = mpsc::channel(4);
+
+            let subscription = tokio::spawn({
+                let hub = hub.clone();
+
+                async move {
+                    service::subscribe_user_entries(&db, &FeedsSettings::stub(), &hub, req, &tx)
+                        .await
+                        .map(|()| db)
+                }
+            });
+
+            // Даём задаче подписаться на хаб
+            tokio::task::yield_now().await;
+
+            hub.publish(&[EntryModel::stub(), live.clone()]);
+
+            assert_eq!(rx.recv().await.transpose()?, Some(live));
+
+            drop(rx);
+
+            let db = subscription.await??;
+
+            // Только подписки на крупные топики
+            assert_eq!(db.into_transaction_log().len(), 1);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_subscribe_user_entries_fanout_on_read() -> Result<(), Error> {
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                cursor_entry_id: Some(entry_id_at(Utc::now().naive_utc() - TimeDelta::minutes(10))),
+            };
+
+            let topic_user = TopicUserModel {
+                event_at: Utc::now().naive_utc() - TimeDelta::hours(1),
+                ..TopicUserModel::new(Uuid::now_v7(), req.user_id, Uuid::now_v7())
+            };
+
+            let stored = MessageTopicModel {
+                fanout_on_read: true,
+                ..MessageTopicModel::new(
+                    Uuid::now_v7(),
+                    Uuid::now_v7(),
+                    topic_user.topic_id,
+                    Utc::now().naive_utc() - TimeDelta::minutes(5),
+                )
+            };
+            let live = MessageTopicModel {
+                fanout_on_read: true,
+                ..MessageTopicModel::new(
+                    Uuid::now_v7(),
+                    Uuid::now_v7(),
+                    topic_user.topic_id,
+                    Utc::now().naive_utc(),
+                )
+            };
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[topic_user.clone()]])
+                .append_query_results([Vec::<EntryModel>::new()])
+                .append_query_results([[stored.clone()]])
+                .into_connection();
+
+            let hub = Hub::new(16);
+            let (tx, mut rx) = mpsc::channel(4);
+
+            let subscription = tokio::spawn({
+                let hub = hub.clone();
+
+                async move {
+                    service::subscribe_user_entries(&db, &FeedsSettings::stub(), &hub, req, &tx)
+                        .await
+                }
+            });
+
+            assert_eq!(
+                rx.recv().await.transpose()?,
+                Some(EntryModel::from_message_topic(stored, &topic_user))
+            );
+
+            hub.publish_message_topic(&live);
+
+            assert_eq!(
+                rx.recv().await.transpose()?,
+                Some(EntryModel::from_message_topic(live, &topic_user))
+            );
+
+            drop(rx);
+
+            subscription.await??;
+
+            Ok(())
+        }
+    }
+}
+
+// Маркер ставится только на запись из ленты пользователя: чужой или выдуманный id из будущего
+// навсегда спрятал бы все новые записи, ведь маркер не откатывается
+pub async fn mark_read(
+    db: &DbConn,
+    settings: &FeedsSettings,
+    req: mark_read::Request,
+) -> Result<(), AppError> {
+    if !repo::has_entry(db, req.user_id, req.entry_id).await? {
+        let topics_users = repo::get_fanout_topics_users_by_user_id(
+            db,
+            req.user_id,
+            None,
+            settings.fanout.max_topics,
+        )
+        .await?;
+
+        if topics_users.is_empty()
+            || !repo::has_message_topic_fanout_on_read(db, &topics_users, req.entry_id).await?
+        {
+            return Err(AppError::NotFound);
+        }
+    }
+
+    repo::upsert_read_marker(db, ReadMarkerModel::new(req.user_id, req.entry_id)).await
+}
+
+pub mod mark_read {
+    use uuid::Uuid;
+
+    pub struct Request {
+        pub user_id: Uuid,
+        pub entry_id: Uuid,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use chrono::Utc;
+        use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+        use uuid::Uuid;
+
+        use crate::app::{
+            error::AppError,
+            feeds::{
+                repo::{EntryModel, MessageTopicModel, TopicUserModel},
+                service::{self, mark_read::Request},
+                settings::FeedsSettings,
+            },
+        };
+
+        #[tokio::test]
+        async fn test_ok_mark_read() -> Result<(), Error> {
+            let entry = EntryModel::stub();
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[entry.clone()]])
+                .append_exec_results([MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 1,
+                }])
+                .into_connection();
+
+            let req = Request {
+                user_id: entry.user_id,
+                entry_id: entry.entry_id,
+            };
+
+            service::mark_read(&db, &FeedsSettings::stub(), req).await?;
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 2);
+            assert!(
+                log[1]
+                    .statements()
+                    .first()
+                    .is_some_and(|it| it.sql.ends_with(
+                        r#"ON CONFLICT ("user_id") DO UPDATE SET "entry_id" = "excluded"."entry_id", "updated_at" = "excluded"."updated_at" WHERE read_markers.entry_id < excluded.entry_id"#
+                    ))
+            );
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_mark_read_fanout_on_read() -> Result<(), Error> {
+            let user_id = Uuid::now_v7();
+
+            let topic_user = TopicUserModel::new(Uuid::now_v7(), user_id, Uuid::now_v7());
+            let message_topic = MessageTopicModel::new(
+                Uuid::now_v7(),
+                Uuid::now_v7(),
+                topic_user.topic_id,
+                Utc::now().naive_utc(),
+            );
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<EntryModel>::new()])
+                .append_query_results([[topic_user]])
+                .append_query_results([[message_topic.clone()]])
+                .append_exec_results([MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 1,
+                }])
+                .into_connection();
+
+            let req = Request {
+                user_id,
+                entry_id: message_topic.entry_id,
+            };
+
+            service::mark_read(&db, &FeedsSettings::stub(), req).await?;
+
+            assert_eq!(db.into_transaction_log().len(), 4);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_err_mark_read_unknown_entry() -> Result<(), Error> {
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<EntryModel>::new()])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
+
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                entry_id: Uuid::now_v7(),
+            };
+
+            let res = service::mark_read(&db, &FeedsSettings::stub(), req).await;
+
+            assert!(matches!(res, Err(AppError::NotFound)));
+            assert_eq!(db.into_transaction_log().len(), 2);
+
+            Ok(())
+        }
+    }
+}
+
+// Маркер встаёт на самую новую запись ленты, а не на текущее время: запись, id которой выдан
+// раньше, но ещё не закоммичена, иначе оказалась бы прочитанной, так и не попав в ленту
+pub async fn mark_all_read(
+    db: &DbConn,
+    settings: &FeedsSettings,
+    req: mark_all_read::Request,
+) -> Result<(), AppError> {
+    let head_entry_id = get_head_entry_id(db, settings, req.user_id, None).await?;
+
+    if head_entry_id.is_nil() {
+        return Ok(());
+    }
+
+    repo::upsert_read_marker(db, ReadMarkerModel::new(req.user_id, head_entry_id)).await
+}
+
+pub mod mark_all_read {
+    use uuid::Uuid;
+
+    pub struct Request {
+        pub user_id: Uuid,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult, Value};
+        use uuid::Uuid;
+
+        use crate::app::feeds::{
+            repo::{EntryModel, TopicUserModel},
+            service::{self, mark_all_read::Request},
+            settings::FeedsSettings,
+        };
+
+        #[tokio::test]
+        async fn test_ok_mark_all_read() -> Result<(), Error> {
+            let entry = EntryModel::stub();
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[entry.clone()]])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .append_exec_results([MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 1,
+                }])
+                .into_connection();
+
+            let req = Request {
+                user_id: entry.user_id,
+            };
+
+            service::mark_all_read(&db, &FeedsSettings::stub(), req).await?;
+
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 3);
+            assert!(log[2].statements().first().is_some_and(|it| {
+                it.values
+                    .as_ref()
+                    .is_some_and(|values| values.0.contains(&Value::from(entry.entry_id)))
+            }));
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_mark_all_read_empty_feed() -> Result<(), Error> {
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<EntryModel>::new()])
+                .append_query_results([Vec::<TopicUserModel>::new()])
+                .into_connection();
 
-pub mod create_entries_from_message_topic {
-    use uuid::Uuid;
+            let req = Request {
+                user_id: Uuid::now_v7(),
+            };
 
-    use crate::app::feeds::repo::task::CreateMessageTopic;
+            service::mark_all_read(&db, &FeedsSettings::stub(), req).await?;
 
-    pub struct Request {
-        pub message_id: Uuid,
-        pub topic_id: Uuid,
-        pub last_topic_user_id: Option<Uuid>,
-    }
+            assert_eq!(db.into_transaction_log().len(), 2);
 
-    impl From<CreateMessageTopic> for Request {
-        fn from(payload: CreateMessageTopic) -> Self {
-            Self {
-                message_id: payload.message_id,
-                topic_id: payload.topic_id,
-                last_topic_user_id: payload.last_topic_user_id,
-            }
+            Ok(())
         }
     }
 }
 
-pub async fn handle_message_topic(
+pub async fn get_unread_count(
     db: &DbConn,
-    req: handle_message_topic::Request,
-) -> Result<(), AppError> {
-    match req.tp {
-        handle_message_topic::Type::Created => {
-            let task = TaskModel::new(Payload::CreateMessageTopic(req.into()));
-            repo::create_task(db, task).await?;
-        }
-        handle_message_topic::Type::Deleted => {
-            println!("QQQ");
-        }
-    }
-
-    Ok(())
-}
-
-pub mod handle_message_topic {
-    use uuid::Uuid;
-
-    use crate::app::feeds::repo::task::CreateMessageTopic;
+    settings: &FeedsSettings,
+    req: get_unread_count::Request,
+) -> Result<get_unread_count::Response, AppError> {
+    let limit = settings.limits.unread;
 
-    #[derive(Clone)]
-    pub struct Request {
-        pub tp: Type,
-        pub message_topic_id: Uuid,
-        pub topic_id: Uuid,
-        pub message_id: Uuid,
-    }
+    // Без маркера непрочитано всё с момента подписки: записи появляются только после неё,
+    // а сообщения крупных топиков отсекаются по времени подписки
+    let read_entry_id = repo::get_read_marker(db, req.user_id)
+        .await?
+        .map(|it| it.entry_id);
 
-    pub type Type = bzd_messages_api::events::message_topic::Type;
+    // Хватает limit + 1 непрочитанных, чтобы понять, что их больше лимита
+    let entries =
+        repo::get_entries_after_entry_id(db, req.user_id, None, read_entry_id, limit + 1).await?;
 
-    impl From<Request> for CreateMessageTopic {
-        fn from(req: Request) -> Self {
-            Self {
-                message_id: req.message_id,
-                topic_id: req.topic_id,
-                last_topic_user_id: None,
+    let topics_users =
+        repo::get_fanout_topics_users_by_user_id(db, req.user_id, None, settings.fanout.max_topics)
+            .await?;
+
+    let messages_topics = if topics_users.is_empty() {
+        vec![]
+    } else {
+        repo::get_messages_topics_fanout_on_read_after_entry_id(
+            db,
+            &topics_users,
+            read_entry_id,
+            limit + 1,
+        )
+        .await?
+    };
+
+    let topic_ids = if req.by_topic {
+        repo::get_topics_users_by_topic_user_ids(
+            db,
+            entries
+                .iter()
+                .flat_map(|it| it.topic_user_ids.iter().copied())
+                .collect(),
+        )
+        .await?
+        .into_iter()
+        .map(|it| (it.topic_user_id, it.topic_id))
+        .collect()
+    } else {
+        HashMap::new()
+    };
+
+    // Сообщение может прийти и записью, и из крупного топика — считается один раз
+    let mut message_ids = HashSet::new();
+    let mut topics: BTreeMap<Uuid, HashSet<Uuid>> = BTreeMap::new();
+
+    for entry in &entries {
+        message_ids.insert(entry.message_id);
+
+        for topic_user_id in &entry.topic_user_ids {
+            if let Some(topic_id) = topic_ids.get(topic_user_id) {
+                topics
+                    .entry(*topic_id)
+                    .or_default()
+                    .insert(entry.message_id);
             }
         }
     }
-}
 
-pub async fn handle_topic_user(
-    db: &DbConn,
-    req: handle_topic_user::Request,
-) -> Result<(), AppError> {
-    let topic_user: repo::topic_user::Model = req.clone().into();
+    for message_topic in &messages_topics {
+        message_ids.insert(message_topic.message_id);
 
-    match req.tp {
-        handle_topic_user::Type::Created => repo::upsert_topic_user(db, topic_user).await?,
-        handle_topic_user::Type::Deleted => repo::delete_topic_user(db, topic_user).await?,
+        if req.by_topic {
+            topics
+                .entry(message_topic.topic_id)
+                .or_default()
+                .insert(message_topic.message_id);
+        }
     }
 
-    Ok(())
+    let count = u64::try_from(message_ids.len()).map_err(|_| AppError::Unreachable)?;
+    let capped = count > limit;
+
+    // При capped счётчики по топикам — нижняя граница
+    let topics = topics
+        .into_iter()
+        .map(|(topic_id, message_ids)| {
+            let count = u64::try_from(message_ids.len()).map_err(|_| AppError::Unreachable)?;
+
+            Ok(get_unread_count::TopicCount {
+                topic_id,
+                count: count.min(limit),
+            })
+        })
+        .collect::<Result<_, AppError>>()?;
+
+    Ok(get_unread_count::Response {
+        count: count.min(limit),
+        capped,
+        topics,
+    })
 }
 
-pub mod handle_topic_user {
+pub mod get_unread_count {
     use uuid::Uuid;
 
-    use crate::app::feeds::repo;
-
-    #[derive(Clone)]
     pub struct Request {
-        pub tp: Type,
-        pub topic_user_id: Uuid,
-        pub topic_id: Uuid,
         pub user_id: Uuid,
+        pub by_topic: bool,
     }
 
-    impl From<Request> for repo::topic_user::Model {
-        fn from(req: Request) -> Self {
-            Self::new(req.topic_user_id, req.user_id, req.topic_id)
-        }
+    pub struct Response {
+        pub count: u64,
+        pub capped: bool,
+        pub topics: Vec<TopicCount>,
     }
 
-    pub type Type = bzd_messages_api::events::topic_user::Type;
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct TopicCount {
+        pub topic_id: Uuid,
+        pub count: u64,
+    }
 
     #[cfg(test)]
     mod tests {
         use bzd_lib::error::Error;
-        use bzd_messages_api::events::topic_user::Type;
-        use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult, Transaction};
+        use chrono::Utc;
+        use sea_orm::{DatabaseBackend, MockDatabase, Transaction};
         use uuid::Uuid;
 
-        use crate::app::feeds::service::{self, handle_topic_user::Request};
+        use crate::app::feeds::{
+            repo::{EntryModel, MessageTopicModel, ReadMarkerModel, TopicUserModel},
+            service::{
+                self,
+                get_unread_count::{Request, TopicCount},
+            },
+            settings::FeedsSettings,
+        };
 
         #[tokio::test]
-        async fn test_ok_handle_topic_user_delete() -> Result<(), Error> {
+        async fn test_ok_get_unread_count() -> Result<(), Error> {
             let req = Request {
-                tp: Type::Deleted,
-                topic_user_id: Uuid::now_v7(),
-                topic_id: Uuid::now_v7(),
                 user_id: Uuid::now_v7(),
+                by_topic: false,
             };
 
+            let read_marker = ReadMarkerModel::new(req.user_id, Uuid::now_v7());
+
+            let topic_user = TopicUserModel::new(Uuid::now_v7(), req.user_id, Uuid::now_v7());
+
+            let entry = EntryModel::new(req.user_id, Uuid::now_v7(), vec![]);
+
+            // То же сообщение пришло и записью, и из крупного топика
+            let messages_topics = [entry.message_id, Uuid::now_v7()].map(|message_id| {
+                MessageTopicModel::new(
+                    Uuid::now_v7(),
+                    message_id,
+                    topic_user.topic_id,
+                    Utc::now().naive_utc(),
+                )
+            });
+
             let db = MockDatabase::new(DatabaseBackend::Postgres)
-                .append_exec_results([MockExecResult {
-                    last_insert_id: 0,
-                    rows_affected: 1,
-                }])
+                .append_query_results([[read_marker.clone()]])
+                .append_query_results([[entry.clone()]])
+                .append_query_results([[topic_user.clone()]])
+                .append_query_results([messages_topics])
                 .into_connection();
 
-            service::handle_topic_user(&db, req.clone()).await?;
+            let res = service::get_unread_count(&db, &FeedsSettings::stub(), req).await?;
+
+            assert_eq!(res.count, 2);
+            assert!(!res.capped);
+            assert!(res.topics.is_empty());
 
+            let log = db.into_transaction_log();
+
+            assert_eq!(log.len(), 4);
             assert_eq!(
-                db.into_transaction_log(),
-                [Transaction::from_sql_and_values(
+                log[1],
+                Transaction::from_sql_and_values(
                     DatabaseBackend::Postgres,
-                    r#"DELETE FROM "topics_users" WHERE "topics_users"."topic_user_id" = $1"#,
-                    [req.topic_user_id.into()]
-                ),]
+                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 AND "entries"."entry_id" > $2 ORDER BY "entries"."entry_id" ASC LIMIT $3"#,
+                    [
+                        read_marker.user_id.into(),
+                        read_marker.entry_id.into(),
+                        6u64.into()
+                    ]
+                )
             );
 
             Ok(())
         }
 
         #[tokio::test]
-        async fn test_ok_handle_topic_user_create() -> Result<(), Error> {
+        async fn test_ok_get_unread_count_capped() -> Result<(), Error> {
             let req = Request {
-                tp: Type::Created,
-                topic_user_id: Uuid::now_v7(),
-                topic_id: Uuid::now_v7(),
                 user_id: Uuid::now_v7(),
+                by_topic: false,
             };
 
+            let entries: Vec<_> = (0..6)
+                .map(|_| EntryModel::new(req.user_id, Uuid::now_v7(), vec![]))
+                .collect();
+
             let db = MockDatabase::new(DatabaseBackend::Postgres)
-                .append_exec_results([MockExecResult {
-                    last_insert_id: 0,
-                    rows_affected: 1,
-                }])
+                .append_query_results([Vec::<ReadMarkerModel>::new()])
+                .append_query_results([entries])
+                .append_query_results([Vec::<TopicUserModel>::new()])
                 .into_connection();
 
-            service::handle_topic_user(&db, req.clone()).await?;
+            let res = service::get_unread_count(&db, &FeedsSettings::stub(), req).await?;
 
-            // assert_eq!(
-            //     db.into_transaction_log(),
-            //     [Transaction::from_sql_and_values(
-            //         DatabaseBackend::Postgres,
-            //         r#"
-            //             INSERT INTO "topics_users" ("topic_user_id", "user_id", "topic_id", "created_at", "updated_at")
-            //             VALUES ($1, $2, $3, $4, $5) ON CONFLICT ("topic_user_id")
-            //             DO NOTHING RETURNING "topic_user_id"
-            //         "#,
-            //         [
-            //             req.topic_user_id.into(),
-            //             req.user_id.into(),
-            //             req.topic_id.into(),
-            //             ???,
-            //             ???
-            //         ]
-            //     ),]
-            // );
+            assert_eq!(res.count, 5);
+            assert!(res.capped);
+
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_ok_get_unread_count_by_topic() -> Result<(), Error> {
+            let req = Request {
+                user_id: Uuid::now_v7(),
+                by_topic: true,
+            };
+
+            let topic_user = TopicUserModel::new(Uuid::now_v7(), req.user_id, Uuid::now_v7());
+            let other_topic_user = TopicUserModel::new(Uuid::now_v7(), req.user_id, Uuid::now_v7());
+
+            let entries = vec![
+                EntryModel::new(
+                    req.user_id,
+                    Uuid::now_v7(),
+                    vec![topic_user.topic_user_id, other_topic_user.topic_user_id],
+                ),
+                EntryModel::new(
+                    req.user_id,
+                    Uuid::now_v7(),
+                    vec![other_topic_user.topic_user_id],
+                ),
+            ];
+
+            let message_topic = MessageTopicModel::new(
+                Uuid::now_v7(),
+                Uuid::now_v7(),
+                topic_user.topic_id,
+                Utc::now().naive_utc(),
+            );
+
+            let db = MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([Vec::<ReadMarkerModel>::new()])
+                .append_query_results([entries])
+                .append_query_results([[topic_user.clone()]])
+                .append_query_results([[message_topic]])
+                .append_query_results([[topic_user.clone(), other_topic_user.clone()]])
+                .into_connection();
+
+            let res = service::get_unread_count(&db, &FeedsSettings::stub(), req).await?;
+
+            assert_eq!(res.count, 3);
+            assert!(!res.capped);
+
+            let mut expected = vec![
+                TopicCount {
+                    topic_id: topic_user.topic_id,
+                    count: 2,
+                },
+                TopicCount {
+                    topic_id: other_topic_user.topic_id,
+                    count: 2,
+                },
+            ];
+            expected.sort_by_key(|it| it.topic_id);
+
+            assert_eq!(res.topics, expected);
 
             Ok(())
         }
     }
 }
 
-pub async fn get_user_entries(
+pub async fn get_dead_tasks(
     db: &DbConn,
     settings: &FeedsSettings,
-    req: get_user_entries::Request,
-) -> Result<get_user_entries::Response, AppError> {
-    let limit = settings.limits.user;
+    req: get_dead_tasks::Request,
+) -> Result<get_dead_tasks::Response, AppError> {
+    let limit = settings.limits.tasks;
 
-    let mut entries =
-        repo::get_entries_by_user_id(db, req.user_id, req.cursor_entry_id, limit + 1).await?;
+    let mut tasks = repo::get_dead_tasks(db, req.cursor_task_id, limit + 1).await?;
 
-    let cursor_entry =
-        if entries.len() > usize::try_from(limit).map_err(|_| AppError::Unreachable)? {
-            entries.pop()
-        } else {
-            None
-        };
+    let cursor_task = if tasks.len() > usize::try_from(limit).map_err(|_| AppError::Unreachable)? {
+        tasks.pop()
+    } else {
+        None
+    };
 
-    Ok(get_user_entries::Response {
-        entries,
-        cursor_entry,
-    })
+    Ok(get_dead_tasks::Response { tasks, cursor_task })
 }
 
-pub mod get_user_entries {
+pub mod get_dead_tasks {
     use uuid::Uuid;
 
-    use crate::app::feeds::repo::EntryModel;
+    use crate::app::feeds::repo::TaskModel;
 
-    #[derive(Clone)]
     pub struct Request {
-        pub user_id: Uuid,
-        pub cursor_entry_id: Option<Uuid>,
+        pub cursor_task_id: Option<Uuid>,
     }
 
     pub struct Response {
-        pub entries: Vec<EntryModel>,
-        pub cursor_entry: Option<EntryModel>,
+        pub tasks: Vec<TaskModel>,
+        pub cursor_task: Option<TaskModel>,
     }
 
     #[cfg(test)]
     mod tests {
-        use bzd_lib::{error::Error, settings::NATSConsumerSettings};
+        use bzd_lib::error::Error;
         use sea_orm::{DatabaseBackend, MockDatabase, Transaction};
         use uuid::Uuid;
 
         use crate::app::feeds::{
-            repo::EntryModel,
-            service::{self, get_user_entries::Request},
-            settings::{FeedsSettings, LimitsSettings, MessagingSettings, ProcessingSettings},
+            repo::{
+                TaskModel,
+                task::{Payload, PurgeTopicUser, Status},
+            },
+            service::{self, get_dead_tasks::Request},
+            settings::FeedsSettings,
         };
 
+        fn dead_task() -> TaskModel {
+            let mut task = TaskModel::new(Payload::PurgeTopicUser(PurgeTopicUser {
+                topic_user_id: Uuid::now_v7(),
+                user_id: Uuid::now_v7(),
+                last_entry_id: None,
+            }));
+            task.status = Status::Dead;
+
+            task
+        }
+
         #[tokio::test]
-        async fn test_ok_get_without_cursor() -> Result<(), Error> {
-            let entries = vec![EntryModel::stub(); 5];
+        async fn test_ok_get_dead_tasks() -> Result<(), Error> {
+            let mut settings = FeedsSettings::stub();
+            settings.limits.tasks = 2;
+
+            let tasks = vec![dead_task(), dead_task(), dead_task()];
 
             let db = MockDatabase::new(DatabaseBackend::Postgres)
-                .append_query_results([entries.clone()])
+                .append_query_results([tasks.clone()])
                 .into_connection();
 
             let req = Request {
-                user_id: Uuid::now_v7(),
-                cursor_entry_id: None,
+                cursor_task_id: None,
             };
 
-            let settings = test_settings(4);
-
-            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
-
-            assert_eq!(res.entries.len(), 4);
-            assert_eq!(res.entries.first(), entries.first());
-            assert_eq!(res.cursor_entry.as_ref(), entries.last());
+            let res = service::get_dead_tasks(&db, &settings, req).await?;
 
-            assert_eq!(
-                db.into_transaction_log(),
-                [Transaction::from_sql_and_values(
-                    DatabaseBackend::Postgres,
-                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 ORDER BY "entries"."entry_id" DESC LIMIT $2"#,
-                    [req.user_id.into(), (settings.limits.user + 1).into()]
-                ),]
-            );
+            assert_eq!(res.tasks, tasks[..2]);
+            // Последняя лишняя задача становится курсором следующей страницы
+            assert_eq!(res.cursor_task, Some(tasks[2].clone()));
 
             Ok(())
         }
 
         #[tokio::test]
-        async fn test_ok_get_without_cursor_and_last() -> Result<(), Error> {
-            let entries = vec![EntryModel::stub(); 4];
+        async fn test_ok_get_dead_tasks_from_cursor() -> Result<(), Error> {
+            let mut settings = FeedsSettings::stub();
+            settings.limits.tasks = 2;
+
+            let task = dead_task();
 
             let db = MockDatabase::new(DatabaseBackend::Postgres)
-                .append_query_results([entries.clone()])
+                .append_query_results([[task.clone()]])
                 .into_connection();
 
             let req = Request {
-                user_id: Uuid::now_v7(),
-                cursor_entry_id: None,
+                cursor_task_id: Some(task.task_id),
             };
 
-            let settings = test_settings(4);
-
-            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+            let res = service::get_dead_tasks(&db, &settings, req).await?;
 
-            assert_eq!(res.entries.len(), 4);
-            assert_eq!(res.entries.first(), entries.first());
-            assert_eq!(res.cursor_entry, None);
+            assert_eq!(res.tasks, vec![task.clone()]);
+            assert_eq!(res.cursor_task, None);
 
             assert_eq!(
                 db.into_transaction_log(),
                 [Transaction::from_sql_and_values(
                     DatabaseBackend::Postgres,
-                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 ORDER BY "entries"."entry_id" DESC LIMIT $2"#,
-                    [req.user_id.into(), (settings.limits.user + 1).into()]
-                ),]
+                    r#"SELECT "tasks"."task_id", "tasks"."payload", "tasks"."created_at", "tasks"."updated_at", "tasks"."locked_at", "tasks"."locked_by", "tasks"."status", "tasks"."attempts", "tasks"."last_error", "tasks"."next_run_at" FROM "tasks" WHERE "tasks"."status" = $1 AND "tasks"."task_id" >= $2 ORDER BY "tasks"."task_id" ASC LIMIT $3"#,
+                    ["dead".into(), task.task_id.into(), 3u64.into()]
+                )]
             );
 
             Ok(())
         }
+    }
+}
 
-        #[tokio::test]
-        async fn test_ok_get_with_cursor() -> Result<(), Error> {
-            let entries = vec![EntryModel::stub(); 5];
+pub async fn requeue_task(db: &DbConn, req: requeue_task::Request) -> Result<(), AppError> {
+    if !repo::requeue_task(db, req.task_id).await? {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(())
+}
+
+pub mod requeue_task {
+    use uuid::Uuid;
+
+    pub struct Request {
+        pub task_id: Uuid,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use bzd_lib::error::Error;
+        use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+        use uuid::Uuid;
+
+        use crate::app::{
+            error::AppError,
+            feeds::service::{self, requeue_task::Request},
+        };
 
+        #[tokio::test]
+        async fn test_ok_requeue_task() -> Result<(), Error> {
             let db = MockDatabase::new(DatabaseBackend::Postgres)
-                .append_query_results([entries.clone()])
+                .append_exec_results([
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 1,
+                    },
+                    MockExecResult {
+                        last_insert_id: 0,
+                        rows_affected: 0,
+                    },
+                ])
                 .into_connection();
 
             let req = Request {
-                user_id: Uuid::now_v7(),
-                cursor_entry_id: Some(Uuid::now_v7()),
+                task_id: Uuid::now_v7(),
             };
 
-            let settings = test_settings(4);
-
-            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
+            service::requeue_task(&db, req).await?;
 
-            assert_eq!(res.entries.len(), 4);
-            assert_eq!(res.entries.first(), entries.first());
-            assert_eq!(res.cursor_entry.as_ref(), entries.last());
+            let log = db.into_transaction_log();
 
+            assert_eq!(log.len(), 2);
+            assert!(
+                log[0]
+                    .statements()
+                    .first()
+                    .is_some_and(|it| it.sql.starts_with(r#"UPDATE "tasks" SET"#))
+            );
+            // Обработчик задач просыпается сразу, а не на следующем опросе
             assert_eq!(
-                db.into_transaction_log(),
-                [Transaction::from_sql_and_values(
-                    DatabaseBackend::Postgres,
-                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 AND "entries"."entry_id" <= $2 ORDER BY "entries"."entry_id" DESC LIMIT $3"#,
-                    [
-                        req.user_id.into(),
-                        req.cursor_entry_id.into(),
-                        (settings.limits.user + 1).into()
-                    ]
-                ),]
+                log[1].statements().first().map(|it| it.sql.as_str()),
+                Some("NOTIFY bzd_flux_tasks")
             );
 
             Ok(())
         }
 
         #[tokio::test]
-        async fn test_ok_get_with_cursor_and_last() -> Result<(), Error> {
-            let entries = vec![EntryModel::stub(); 4];
-
+        async fn test_err_requeue_task_not_dead() -> Result<(), Error> {
             let db = MockDatabase::new(DatabaseBackend::Postgres)
-                .append_query_results([entries.clone()])
+                .append_exec_results([MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 0,
+                }])
                 .into_connection();
 
             let req = Request {
-                user_id: Uuid::now_v7(),
-                cursor_entry_id: Some(Uuid::now_v7()),
+                task_id: Uuid::now_v7(),
             };
 
-            let settings = test_settings(4);
-
-            let res = service::get_user_entries(&db, &settings, req.clone()).await?;
-
-            assert_eq!(res.entries.len(), 4);
-            assert_eq!(res.entries.first(), entries.first());
-            assert_eq!(res.cursor_entry.as_ref(), None);
+            let res = service::requeue_task(&db, req).await;
 
-            assert_eq!(
-                db.into_transaction_log(),
-                [Transaction::from_sql_and_values(
-                    DatabaseBackend::Postgres,
-                    r#"SELECT "entries"."entry_id", "entries"."user_id", "entries"."message_id", "entries"."topic_user_ids", "entries"."created_at", "entries"."updated_at" FROM "entries" WHERE "entries"."user_id" = $1 AND "entries"."entry_id" <= $2 ORDER BY "entries"."entry_id" DESC LIMIT $3"#,
-                    [
-                        req.user_id.into(),
-                        req.cursor_entry_id.into(),
-                        (settings.limits.user + 1).into()
-                    ]
-                ),]
-            );
+            assert!(matches!(res, Err(AppError::NotFound)));
+            assert_eq!(db.into_transaction_log().len(), 1);
 
             Ok(())
         }
-
-        fn test_settings(limit: u64) -> FeedsSettings {
-            FeedsSettings {
-                limits: LimitsSettings { user: limit },
-                messaging: MessagingSettings {
-                    messages_topics: NATSConsumerSettings {
-                        subjects: vec![],
-                        consumer: String::new(),
-                    },
-                    topics_users: NATSConsumerSettings {
-                        subjects: vec![],
-                        consumer: String::new(),
-                    },
-                },
-                processing: ProcessingSettings { batch_size: 0 },
-            }
-        }
     }
 }
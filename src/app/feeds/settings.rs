@@ -1,25 +1,295 @@
 use bzd_lib::settings::NATSConsumerSettings;
 use serde::Deserialize;
+use validator::{Validate, ValidationError};
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, Validate)]
 pub struct FeedsSettings {
     pub limits: LimitsSettings,
     pub messaging: MessagingSettings,
+    #[validate(nested)]
     pub processing: ProcessingSettings,
+    pub unsubscribe: UnsubscribeSettings,
+    pub fanout: FanoutSettings,
+    #[validate(nested)]
+    pub outbox: OutboxSettings,
+    #[validate(nested)]
+    pub inbox: InboxSettings,
+    #[validate(nested)]
+    pub streaming: StreamingSettings,
+    #[validate(nested)]
+    pub pagination: PaginationSettings,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct LimitsSettings {
     pub user: u64,
+    pub backfill: u64,
+    pub tasks: u64,
+    pub unread: u64,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct MessagingSettings {
     pub messages_topics: NATSConsumerSettings,
     pub topics_users: NATSConsumerSettings,
+    pub nak_delay_secs: u64,
+    pub max_deliveries: i64,
+    pub batch: BatchSettings,
+    pub dead_letter: DeadLetterSettings,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BatchSettings {
+    pub size: usize,
+    pub wait_ms: u64,
 }
 
 #[derive(Deserialize, Clone)]
+pub struct DeadLetterSettings {
+    pub subject: String,
+    pub stream: String,
+    pub consumer: String,
+}
+
+#[derive(Deserialize, Clone, Validate)]
 pub struct ProcessingSettings {
     pub batch_size: u64,
+    // При нуле семафор не выдаст ни одного разрешения и обработка встанет
+    #[validate(range(min = 1))]
+    pub concurrency: usize,
+    // tokio::time::interval не принимает нулевой период
+    #[validate(range(min = 1))]
+    pub interval_secs: u64,
+    pub lock_secs: u64,
+    pub max_attempts: i32,
+    pub backoff_base_secs: u64,
+    pub backoff_max_secs: u64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct UnsubscribeSettings {
+    pub policy: UnsubscribePolicy,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnsubscribePolicy {
+    Keep,
+    Retract,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct FanoutSettings {
+    pub on_read_threshold: u64,
+    // Как часто пересчитывать подписчиков топика, который ещё рассылается при записи
+    pub check_interval_secs: u64,
+    // Сколько подписок на крупные топики учитывается при чтении одной ленты
+    pub max_topics: u64,
+}
+
+#[derive(Deserialize, Clone, Validate)]
+pub struct OutboxSettings {
+    pub subject: String,
+    pub source: String,
+    pub batch_size: u64,
+    #[validate(range(min = 1))]
+    pub interval_ms: u64,
+}
+
+#[derive(Deserialize, Clone, Validate)]
+pub struct InboxSettings {
+    // Должен быть заметно больше окна повторной доставки и replay, иначе дубли снова пройдут
+    pub ttl_secs: u64,
+    pub batch_size: u64,
+    #[validate(range(min = 1))]
+    pub interval_secs: u64,
+}
+
+#[derive(Deserialize, Clone, Validate)]
+pub struct StreamingSettings {
+    // broadcast::channel и mpsc::channel паникуют на нулевой ёмкости.
+    // capacity — очередь хаба для каждого пользователя с открытым потоком
+    #[validate(range(min = 1))]
+    pub capacity: usize,
+    #[validate(range(min = 1))]
+    pub buffer: usize,
+    // Насколько запись может закоммититься позже своего entry_id
+    pub reorder_window_secs: u64,
+}
+
+#[derive(Deserialize, Clone, Validate)]
+#[validate(schema(function = "validate_page_sizes"))]
+pub struct PaginationSettings {
+    // Ключ HMAC для токенов страниц; без значения по умолчанию
+    #[validate(length(min = 32))]
+    pub token_key: String,
+    #[validate(range(min = 1))]
+    pub min_page_size: u64,
+    pub max_page_size: u64,
+    // Сколько топиков можно передать в фильтре ленты
+    pub max_filter_topic_ids: usize,
+}
+
+// Иначе clamp размера страницы паникует на каждом запросе
+fn validate_page_sizes(settings: &PaginationSettings) -> Result<(), ValidationError> {
+    if settings.min_page_size > settings.max_page_size {
+        return Err(ValidationError::new("min_page_size_gt_max_page_size"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+impl FeedsSettings {
+    pub fn stub() -> Self {
+        Self {
+            limits: LimitsSettings {
+                user: 25,
+                backfill: 10,
+                tasks: 25,
+                unread: 5,
+            },
+            messaging: MessagingSettings {
+                messages_topics: NATSConsumerSettings {
+                    subjects: vec![],
+                    consumer: String::new(),
+                },
+                topics_users: NATSConsumerSettings {
+                    subjects: vec![],
+                    consumer: String::new(),
+                },
+                nak_delay_secs: 5,
+                max_deliveries: 5,
+                batch: BatchSettings {
+                    size: 1,
+                    wait_ms: 100,
+                },
+                dead_letter: DeadLetterSettings {
+                    subject: String::new(),
+                    stream: String::new(),
+                    consumer: String::new(),
+                },
+            },
+            processing: ProcessingSettings {
+                batch_size: 0,
+                concurrency: 1,
+                interval_secs: 3,
+                lock_secs: 5,
+                max_attempts: 5,
+                backoff_base_secs: 1,
+                backoff_max_secs: 60,
+            },
+            unsubscribe: UnsubscribeSettings {
+                policy: UnsubscribePolicy::Keep,
+            },
+            fanout: FanoutSettings {
+                on_read_threshold: 1000,
+                check_interval_secs: 60,
+                max_topics: 100,
+            },
+            outbox: OutboxSettings {
+                subject: "bzd.flux.events.entries".into(),
+                source: "bzd-flux".into(),
+                batch_size: 100,
+                interval_ms: 500,
+            },
+            inbox: InboxSettings {
+                ttl_secs: 86400,
+                batch_size: 100,
+                interval_secs: 60,
+            },
+            streaming: StreamingSettings {
+                capacity: 16,
+                buffer: 4,
+                reorder_window_secs: 60,
+            },
+            pagination: PaginationSettings {
+                token_key: "test-page-token-key-test-page-token-key".into(),
+                min_page_size: 1,
+                max_page_size: 100,
+                max_filter_topic_ids: 10,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use validator::Validate as _;
+
+    use crate::app::feeds::settings::FeedsSettings;
+
+    #[test]
+    fn test_err_validate_zero_concurrency() {
+        let mut settings = FeedsSettings::stub();
+
+        assert!(settings.validate().is_ok());
+
+        settings.processing.concurrency = 0;
+
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_err_validate_zero_processing_interval() {
+        let mut settings = FeedsSettings::stub();
+
+        settings.processing.interval_secs = 0;
+
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_err_validate_zero_outbox_interval() {
+        let mut settings = FeedsSettings::stub();
+
+        settings.outbox.interval_ms = 0;
+
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_err_validate_zero_inbox_interval() {
+        let mut settings = FeedsSettings::stub();
+
+        settings.inbox.interval_secs = 0;
+
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_err_validate_zero_streaming_channels() {
+        let mut settings = FeedsSettings::stub();
+
+        settings.streaming.capacity = 0;
+
+        assert!(settings.validate().is_err());
+
+        settings = FeedsSettings::stub();
+        settings.streaming.buffer = 0;
+
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_err_validate_page_sizes() {
+        let mut settings = FeedsSettings::stub();
+
+        settings.pagination.min_page_size = 0;
+
+        assert!(settings.validate().is_err());
+
+        settings.pagination.min_page_size = settings.pagination.max_page_size + 1;
+
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_err_validate_short_token_key() {
+        let mut settings = FeedsSettings::stub();
+
+        settings.pagination.token_key = "short".into();
+
+        assert!(settings.validate().is_err());
+    }
 }
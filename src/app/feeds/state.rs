@@ -1,8 +1,13 @@
-use crate::app::{db::DbState, feeds::settings::FeedsSettings, mess::MessState};
+use crate::app::{
+    db::DbState,
+    feeds::{hub::Hub, settings::FeedsSettings},
+    mess::MessState,
+};
 
 #[derive(Clone)]
 pub struct FeedsState {
     pub settings: FeedsSettings,
     pub db: DbState,
     pub mess: MessState,
+    pub hub: Hub,
 }
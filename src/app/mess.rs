@@ -1,10 +1,14 @@
 use std::sync::Arc;
 
-use async_nats::jetstream::{self, Context};
+use async_nats::{
+    Client,
+    jetstream::{self, Context},
+};
 use bzd_lib::{error::Error, settings::NATSSettings};
 
 #[derive(Clone)]
 pub struct MessState {
+    pub nats: Client,
     pub js: Arc<JS>,
     pub settings: NATSSettings,
 }
@@ -12,9 +16,10 @@ pub struct MessState {
 impl MessState {
     pub async fn new(settings: &NATSSettings) -> Result<Self, Error> {
         let nats = async_nats::connect(&settings.endpoint).await?;
-        let js = Arc::new(jetstream::new(nats));
+        let js = Arc::new(jetstream::new(nats.clone()));
 
         Ok(Self {
+            nats,
             js,
             settings: settings.clone(),
         })
@@ -1,6 +1,13 @@
 use bzd_lib::error::Error;
+use validator::Validate as _;
 
-use crate::app::{db::DbState, feeds::state::FeedsState, mess::MessState, settings::AppSettings};
+use crate::app::{
+    db::DbState,
+    error::AppError,
+    feeds::{hub::Hub, state::FeedsState},
+    mess::MessState,
+    settings::AppSettings,
+};
 
 #[derive(Clone)]
 pub struct AppState {
@@ -9,6 +16,8 @@ pub struct AppState {
 
 impl AppState {
     pub async fn new(settings: AppSettings) -> Result<Self, Error> {
+        settings.feeds.validate().map_err(AppError::from)?;
+
         let db = DbState::new(&settings.db).await?;
 
         let mess = MessState::new(&settings.nats).await?;
@@ -17,6 +26,7 @@ impl AppState {
             settings: settings.feeds.clone(),
             db: db.clone(),
             mess: mess.clone(),
+            hub: Hub::new(settings.feeds.streaming.capacity),
         };
 
         Ok(Self { feeds })
@@ -6,7 +6,10 @@ mod app;
 async fn main() -> Result<(), Error> {
     bzd_lib::tracing::init()?;
 
-    app::run().await?;
+    match std::env::args().nth(1).as_deref() {
+        Some("replay-dead-letters") => app::replay().await?,
+        _ => app::run().await?,
+    }
 
     Ok(())
 }